@@ -1,6 +1,13 @@
 pub mod sircle;
+pub mod bytes;
+pub mod random;
+pub mod published;
 
 /// Basic math functions
+///
+/// Simulation only uses adding, multiplying and comparing of floats, these are exact by IEEE 754
+/// so every machine gets the same bits. Functions like sqrt, sin or powf are not used by the
+/// physic code because they can differ between platforms.
 pub struct Math;
 impl Math {
     /// Distance between 2 vectors.
@@ -9,6 +16,16 @@ impl Math {
         let b = (point_a[1]-point_b[1]).abs();
         (a*a+b*b).sqrt()
     }
+    /// Squared distance between 2 vectors.
+    pub fn distance_squared(point_a: &[f32;2],point_b: &[f32;2]) -> f32 {
+        let a = point_a[0]-point_b[0];
+        let b = point_a[1]-point_b[1];
+        a*a+b*b
+    }
+    /// If the points are closer then range (or exactly at range), without using sqrt.
+    pub fn within(point_a: &[f32;2],point_b: &[f32;2],range: f32) -> bool {
+        Self::distance_squared(point_a,point_b) <= range*range
+    }
     /// Result of adding two vectors.
     pub fn add_vec(a: &[f32;2],b: &[f32;2]) -> [f32;2] {
         [a[0] + b[0],a[1] + b[1]]
@@ -0,0 +1,131 @@
+/// FNV-1a hash, unlike the std hasher it is the same on every machine and version.
+pub fn checksum(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+/// Builds little endian binary messages, used for map snapshots send over the network.
+#[derive(Default)]
+pub struct ByteWriter {
+    bytes: Vec<u8>,
+}
+impl ByteWriter {
+    pub fn new() -> ByteWriter {
+        ByteWriter {
+            bytes: Vec::new(),
+        }
+    }
+    pub fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+    pub fn bool(&mut self, value: bool) {
+        self.bytes.push(value as u8);
+    }
+    pub fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    pub fn u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    /// Floats are stored by theyr bits so they come back exactly the same.
+    pub fn f32(&mut self, value: f32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    pub fn vec2(&mut self, value: &[f32;2]) {
+        self.f32(value[0]);
+        self.f32(value[1]);
+    }
+    /// Text prefixed by its length, longer text then u16::MAX bytes is cut on a character
+    /// boundary so the rest of the message still reads right.
+    pub fn string(&mut self, value: &str) {
+        let value = &value[..value.floor_char_boundary(u16::MAX as usize)];
+        self.bytes.extend_from_slice(&(value.len() as u16).to_le_bytes());
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+    pub fn bytes(&mut self, value: &[u8]) {
+        self.bytes.extend_from_slice(value);
+    }
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+/// Reads values written by ByteWriter, every read returns None when there is not enough bytes left.
+pub struct ByteReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+impl<'a> ByteReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> ByteReader<'a> {
+        ByteReader {
+            bytes,
+            position: 0,
+        }
+    }
+    fn take<const N: usize>(&mut self) -> Option<[u8;N]> {
+        let out = self.bytes.get(self.position..self.position + N)?.try_into().ok()?;
+        self.position += N;
+        Some(out)
+    }
+    pub fn u8(&mut self) -> Option<u8> {
+        Some(self.take::<1>()?[0])
+    }
+    pub fn bool(&mut self) -> Option<bool> {
+        match self.u8()? {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None,
+        }
+    }
+    pub fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take()?))
+    }
+    pub fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.take()?))
+    }
+    pub fn f32(&mut self) -> Option<f32> {
+        Some(f32::from_le_bytes(self.take()?))
+    }
+    pub fn vec2(&mut self) -> Option<[f32;2]> {
+        Some([self.f32()?,self.f32()?])
+    }
+    pub fn string(&mut self) -> Option<String> {
+        let length = u16::from_le_bytes(self.take()?) as usize;
+        let text = self.bytes.get(self.position..self.position + length)?;
+        self.position += length;
+        String::from_utf8(text.to_vec()).ok()
+    }
+    /// Everything that wasnt read yet.
+    pub fn rest(&mut self) -> &'a [u8] {
+        let out = &self.bytes[self.position.min(self.bytes.len())..];
+        self.position = self.bytes.len();
+        out
+    }
+    /// If every byte was already read.
+    pub fn is_empty(&self) -> bool {
+        self.position >= self.bytes.len()
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_strings_are_cut() {
+        let limit = u16::MAX as usize;
+        // Last character doesnt fit whole, it is left out
+        let cut = format!("{}ž","x".repeat(limit - 1));
+        for (text,expected) in [("x".repeat(limit),"x".repeat(limit)),("x".repeat(limit + 1),"x".repeat(limit)),(cut,"x".repeat(limit - 1))] {
+            let mut out = ByteWriter::new();
+            out.string(&text);
+            out.u32(7);
+            let bytes = out.into_bytes();
+            let mut input = ByteReader::new(&bytes);
+            assert_eq!(input.string(),Some(expected));
+            assert_eq!(input.u32(),Some(7));
+            assert!(input.is_empty());
+        }
+    }
+}
@@ -0,0 +1,26 @@
+use std::sync::{
+    Arc,
+    Mutex,
+    PoisonError,
+};
+
+/// Newest value written by one thread for any number of readers. Lock is held only while the
+/// pointer is swapped or cloned, readers keep theyr copy as long as they need without holding up
+/// the writer.
+pub struct Published<T> {
+    value: Mutex<Arc<T>>,
+}
+impl<T> Published<T> {
+    pub fn new(value: T) -> Published<T> {
+        Published {
+            value: Mutex::new(Arc::new(value)),
+        }
+    }
+    /// Replaces the value, readers that already have the old one keep it.
+    pub fn publish(&self, value: T) {
+        *self.value.lock().unwrap_or_else(PoisonError::into_inner) = Arc::new(value);
+    }
+    pub fn get(&self) -> Arc<T> {
+        Arc::clone(&self.value.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+}
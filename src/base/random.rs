@@ -0,0 +1,39 @@
+/// Xorshift generator, fast and good enough for anything that isnt security or the simulation.
+pub struct Random {
+    state: u64,
+}
+impl Random {
+    /// Seeded from the clock.
+    pub fn new() -> Random {
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0,|time|time.as_nanos() as u64);
+        Self::seeded(seed)
+    }
+    /// Same seed gives the same numbers.
+    pub fn seeded(seed: u64) -> Random {
+        Random {
+            state: seed | 1,
+        }
+    }
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+    /// Number from 0 to limit (without it).
+    pub fn below(&mut self, limit: u64) -> u64 {
+        self.next_u64() % limit.max(1)
+    }
+    /// True with the probability from 0 to 1.
+    pub fn chance(&mut self, probability: f32) -> bool {
+        let uniform = (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+        uniform < probability as f64
+    }
+}
+impl Default for Random {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -14,11 +14,12 @@ pub struct Sircle {
 }
 impl Sircle {
     pub fn overlap(&self,se_p: &[f32;2], sircle: &Self, en_p: &[f32;2]) -> bool {
-        self.radius + sircle.radius>=Math::distance(
+        Math::within(
             &Math::add_vec(&self.position,&se_p)
             ,
             &Math::add_vec(&sircle.position,&en_p)
-            )
+            ,
+            self.radius + sircle.radius)
     }
     pub fn draw(&self,dis: &mut Display<WindowSurface>,frm: &mut glium::Frame,
         position: &[f32;2],
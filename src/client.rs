@@ -1,4 +1,9 @@
 pub mod renderer;
+pub mod prediction;
+pub mod overlay;
+pub mod clock;
+pub mod bot;
+pub mod reconnect;
 
 use winit::event_loop::EventLoop;
 use winit::event::{
@@ -7,9 +12,27 @@ use winit::event::{
 };
 
 use crate::game::networking::*;
+use crate::game::transport::*;
 use crate::game::physic::Direction;
 use crate::client::renderer::GameRanderer;
-use crate::game::map::Map;
+use crate::client::prediction::Prediction;
+use crate::client::overlay::ChatOverlay;
+use crate::client::clock::ClockSync;
+use crate::client::bot::*;
+use crate::client::reconnect::Reconnect;
+use crate::game::clock::TimeResponse;
+use crate::game::simulator::*;
+use crate::game::rollback::*;
+use crate::game::replay::Replay;
+use crate::game::room::RoomInfo;
+use crate::base::bytes::ByteWriter;
+use crate::game::map::{
+    Map,
+    MapInformation,
+};
+use crate::game::character::Character;
+use crate::game::manifest::*;
+use crate::game::assets::download_missing;
 use std::collections::HashMap;
 use glium::backend::glutin::SimpleWindowBuilder;
 
@@ -33,9 +56,16 @@ enum InputEvents {
 
     Switch(Option<u32>),
 
+    /// Opens chat input, Enter sends the message and Escape closes it.
+    Chat,
     Quit,
 }
 
+/// Reason of a failed request, the message send by the server or just the status.
+fn failure(status: reqwest::StatusCode, body: &str) -> String {
+    ErrorResponse::from_string(body).map_or(status.to_string(),|error|error.message)
+}
+
 /// Main object that manages logic for the client that connects to the physic server.
 /// Object contains information about how to connect to server, how player interacts with the game,
 /// and options how to render.
@@ -54,6 +84,13 @@ pub struct Client {
     refresh_rate: f32,
 
     render: crate::client::renderer::RenderOptions,
+    rollback: Option<RollbackSettings>,
+    /// Room on the server that is joined.
+    room: u32,
+    /// If set, the room is only watched with this many ticks of delay.
+    spectate: Option<usize>,
+    /// Simulated bad network for everything send over UDP.
+    network: NetworkConditions,
 }
 impl Client {
     pub fn new(password: String, name: String, addres: String, refresh_rate: f32, assets: String) -> Client {
@@ -78,6 +115,7 @@ impl Client {
         input_map.insert(KeyCode::KeyQ,InputEvents::Special);
 
         input_map.insert(KeyCode::Escape,InputEvents::Quit);
+        input_map.insert(KeyCode::Enter,InputEvents::Chat);
 
         input_map.insert(KeyCode::Backspace,InputEvents::Switch(Option::None));
         input_map.insert(KeyCode::Digit0,InputEvents::Switch(Some(0)));
@@ -96,8 +134,94 @@ impl Client {
             refresh_rate,
 
             render: crate::client::renderer::RenderOptions::new(),
+            rollback: Option::None,
+            room: 0,
+            spectate: Option::None,
+            network: NetworkConditions::default(),
         }
     } 
+    /// Simulates the whole map localy from inputs of all players, server has to run in rollback
+    /// mode with the same settings.
+    pub fn enable_rollback(&mut self, settings: RollbackSettings) {
+        self.rollback = Some(settings);
+    }
+    /// Delays, drops, duplicates and reorders inputs and other packets send to the server.
+    pub fn simulate_network(&mut self, conditions: NetworkConditions) {
+        self.network = conditions;
+    }
+    /// Joins this room instead of the first one.
+    pub fn room(&mut self, room: u32) {
+        self.room = room;
+    }
+    /// Watches the room instead of playing, the match is shown delay ticks late.
+    pub fn spectate(&mut self, delay: usize) {
+        self.spectate = Some(delay);
+    }
+    /// Sends admin command for the room of this client, returns why it was refused.
+    pub fn admin(&self, admin_password: String, command: AdminCommand) -> Result<(),String> {
+        let packet = AdminRequest::new(admin_password,self.room,command).to_string();
+        let response = reqwest::blocking::Client::new()
+            .post(format!("http://{0}/admin/",self.addres))
+            .body(packet)
+            .send().map_err(|error|error.to_string())?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+        Err(failure(status,&response.text().unwrap_or_default()))
+    }
+    /// Asks the server for all its rooms.
+    pub fn list_rooms(&self) -> Option<Vec<RoomInfo>> {
+        let response = reqwest::blocking::Client::new()
+            .get(format!("http://{0}/rooms/",self.addres))
+            .send().ok()?;
+        RoomInfo::list_from_string(&response.text().ok()?)
+    }
+    /// Creates a new room on the server (password of the client has to be the server password),
+    /// the room is then joined with the given room password. Returns why it failed.
+    pub fn create_room(&mut self, name: String, password: String, map_id: usize) -> Result<RoomInfo,String> {
+        let packet = CreateRoomRequest::new(self.password.clone(),name,password.clone(),map_id).to_string();
+        let response = reqwest::blocking::Client::new()
+            .post(format!("http://{0}/rooms/",self.addres))
+            .body(packet)
+            .send().map_err(|error|error.to_string())?;
+        let status = response.status();
+        let body = response.text().map_err(|error|error.to_string())?;
+        if !status.is_success() {
+            return Err(failure(status,&body));
+        }
+        let info = serde_json::from_str::<RoomInfo>(&body).map_err(|error|error.to_string())?;
+        self.room = info.id;
+        self.password = password;
+        Ok(info)
+    }
+    /// Runs this many bots without a window for duration and measures how the server handles
+    /// them. Bots are named after the client, when the room is full new rooms are created.
+    pub fn load_test(&self, bots: usize, duration: std::time::Duration) -> LoadReport {
+        let characters = Character::load_all(None,&self.assets);
+        let manifest = AssetManifest::new(&characters,&MapInformation::load_all(None,&self.assets),&self.assets);
+        // Built in character has no animations, only real ones are played
+        let characters: Vec<u32> = characters.keys().copied().filter(|id|*id != 0).collect();
+        let http = reqwest::blocking::Client::builder()
+            .timeout(Bot::REQUEST_TIMEOUT)
+            .build()
+            .expect("Building http client failed");
+        let handles: Vec<_> = seats(self,bots).into_iter().enumerate()
+            .map(|(index,seat)| {
+                let bot = Bot::new(self,http.clone(),format!("{0}{index}",self.name),seat,manifest.clone(),characters.clone(),duration);
+                thread::spawn(move || bot.run())
+            })
+            .collect();
+        let mut report = LoadReport::default();
+        for handle in handles {
+            match handle.join() {
+                Ok(bot) => report.merge(bot),
+                // Panicked bot still counts, it just didnt measure anything
+                Err(_) => report.bots += 1,
+            }
+        }
+        report
+    }
     /// Enabling custom rendering for debbuging purposes.
     pub fn custom_rendering(&mut self,coliders: bool, hitboxes: bool, hurtboxes: bool) {
         self.render.hitboxes = hitboxes;
@@ -115,18 +239,25 @@ impl Client {
     /// the rendering thread.
     /// All inputs sends from the window are here filtered according to the inputmap and compressed
     /// to a comunication object that is send thourght the network.
-    pub fn start(&mut self) {
+    /// Window is closed when joining the room fails, the reason is returned.
+    pub fn start(&mut self) -> Result<(),String> {
         self.render.assets = self.assets.clone();
 
         let (map_trans, map_rec) = mpsc::channel::<Map>();
+        let (chat_trans, chat_rec) = mpsc::channel::<ChatOverlay>();
         let (input_trans, input_rec) = mpsc::channel::<winit::event::WindowEvent>();
         let addres = self.addres.clone();
         let input_map = self.input_map.clone();
         let (name,password) = (self.name.clone(),self.password.clone());
-        let refresh_rate = self.refresh_rate;
-
-
-        let _network = thread::spawn(move || {
+        let mut refresh_rate = self.refresh_rate;
+        let assets = self.assets.clone();
+        let room = self.room;
+        let spectate = self.spectate;
+        let network = self.network;
+        // Spectators get only confirmed snapshots, there is nothing to simulate
+        let rollback = if spectate.is_some() {Option::None} else {self.rollback};
+
+        let network = thread::spawn(move || {
             let mut input = CharacterInput::new();
             let mut left = false;
             let mut right = false;
@@ -134,16 +265,75 @@ impl Client {
                 Normal,
                 CharacterSwitch(Option<u32>),
                 Join,
+                /// Connection was lost, the same session is tried again.
+                Resume,
+                /// Connection was lost, waiting for the next try.
+                Reconnecting,
                 Quit,
             }
             let mut input_type = InputTypeEvent::Join;
 
-            let frame_time = std::time::Duration::from_secs_f32(1.0/refresh_rate);
+            let (socket,server) = client_socket(&addres).expect("Resolving server addres failed");
+            socket.set_nonblocking(true).expect("Setting UDP socket to non blocking failed");
+            let simulator = NetworkSimulator::new(network,&socket);
+            let mut last_snapshot: u32 = 0;
+            // Events from before joining are not shown.
+            let mut last_event: Option<u32> = Option::None;
+            let mut token = String::new();
+            // Missing assets are downloaded only once, then joining is tried again
+            let mut downloaded = false;
+            let mut chat = ChatOverlay::default();
+            let mut clock = ClockSync::new();
+            // Server tick of the last snapshot and the input it acknowledged
+            let mut synced_with: Option<(u32,u32)> = Option::None;
+            // Tick speed, changed so inputs come to the server just in time
+            let mut speed: f64 = 1.0;
+            let mut frame: usize = 0;
+            // Some while the connection is lost
+            let mut reconnect: Option<Reconnect> = Option::None;
+            let mut last_received = std::time::Instant::now();
+            let mut prediction = Prediction::new(&assets,1.0/refresh_rate,rollback);
+            let mut manifest = AssetManifest::new(&Character::load_all(None,&assets),&MapInformation::load_all(None,&assets),&assets);
+
+            let mut frame_time = std::time::Duration::from_secs_f32(1.0/refresh_rate);
+            // Frames between pings of the server clock
+            const PING_INTERVAL: usize = 30;
             loop {
                 let next_frame = std::time::Instant::now();
 
+                let mut chat_changed = false;
                 for key_input in input_rec.try_iter() {
                     match key_input {
+                        // Open chat input takes every key
+                        WindowEvent::KeyboardInput{event, ..} if chat.typing.is_some() => {
+                            if event.state != ElementState::Pressed {
+                                continue;
+                            }
+                            chat_changed = true;
+                            match event.physical_key {
+                                winit::keyboard::PhysicalKey::Code(KeyCode::Enter) => {
+                                    let text = chat.typing.take().unwrap_or_default();
+                                    if text.trim().is_empty() {
+                                        continue;
+                                    }
+                                    let packet = ChatRequest::new(token.clone(),text).to_string();
+                                    let response = reqwest::blocking::Client::new()
+                                        .post(format!("http://{addres}/chat/"))
+                                        .body(packet)
+                                        .send().map(|response|(response.status(),response.text().unwrap_or_default()));
+                                    match response {
+                                        Ok((status,_)) if status.is_success() => {},
+                                        Ok((status,body)) => chat.push(format!("Message was not send: {}",failure(status,&body))),
+                                        Err(_) => chat.push(String::from("Sending message failed")),
+                                    }
+                                },
+                                winit::keyboard::PhysicalKey::Code(KeyCode::Escape) => chat.typing = Option::None,
+                                winit::keyboard::PhysicalKey::Code(KeyCode::Backspace) => {chat.typing.as_mut().map(|text|text.pop());},
+                                _ => if let Some(text) = &event.text && let Some(typing) = &mut chat.typing {
+                                    typing.extend(text.chars().filter(|character|!character.is_control()));
+                                },
+                            }
+                        },
                         WindowEvent::KeyboardInput{event, ..} => {
                             if let winit::keyboard::PhysicalKey::Code(som)  = event.physical_key && let Some(opt) = input_map.get(&som){
                                 match (opt,event.state) {
@@ -178,46 +368,217 @@ impl Client {
                                     (InputEvents::Switch(targ), ElementState::Pressed) => {input_type = InputTypeEvent::CharacterSwitch(*targ)},
                                     (InputEvents::Switch(_), ElementState::Released) => {},
 
-                                    (InputEvents::Quit,_) => {input_type = InputTypeEvent::Quit;},
+                                    // Character stops while the player writes
+                                    (InputEvents::Chat, ElementState::Pressed) => if spectate.is_none() {
+                                        input = CharacterInput::new();
+                                        (left,right) = (false,false);
+                                        chat.typing = Some(String::new());
+                                        chat_changed = true;
+                                    },
+                                    (InputEvents::Chat, ElementState::Released) => {},
+
+                                    // Escape that closed the chat is released after it
+                                    (InputEvents::Quit, ElementState::Pressed) => {input_type = InputTypeEvent::Quit;},
+                                    (InputEvents::Quit, ElementState::Released) => {},
                                 }
                             }
                         },
+                        WindowEvent::CloseRequested => {input_type = InputTypeEvent::Quit;},
                         _ => {},
                     }
                 }
+                // Lost connection is tried again with backoff instead of sending inputs
+                if let Some(retry) = &reconnect && !matches!(input_type,InputTypeEvent::Quit) {
+                    input_type = match (retry.ready(),token.is_empty()) {
+                        (false,_) => InputTypeEvent::Reconnecting,
+                        (true,true) => InputTypeEvent::Join,
+                        (true,false) => InputTypeEvent::Resume,
+                    };
+                }
                 match input_type {
+                    InputTypeEvent::Normal | InputTypeEvent::CharacterSwitch(_) if spectate.is_some() => {
+                        let mut packet = ByteWriter::new();
+                        packet.string(&token);
+                        Packet::new(PacketKind::Watch,0,last_snapshot,packet.into_bytes()).send_through(&socket,simulator.as_ref(),&server);
+                    },
                     InputTypeEvent::Normal => {
-                        let packet = GameControlPacket::new(password.clone(),name.clone(),input.clone()).to_string();
-                        let response = reqwest::blocking::Client::new()
-                            .put(format!("http://{addres}/map/"))
-                            .body(packet)
-                            .send().unwrap();
-                        let body = response.text().unwrap();
-                        if let Ok(map) = serde_json::from_str::<Map>(&body) {map_trans.send(map).unwrap();}
+                        let sequence = prediction.input(input.clone());
+                        let packet = GameControlPacket::new(token.clone(),prediction.sent_inputs()).as_bytes();
+                        Packet::new(PacketKind::Input,sequence,last_snapshot,packet).send_through(&socket,simulator.as_ref(),&server);
+                        if frame.is_multiple_of(PING_INTERVAL) {
+                            Packet::new(PacketKind::Ping,sequence,last_snapshot,clock.ping(token.clone()).as_bytes()).send_through(&socket,simulator.as_ref(),&server);
+                        }
+                        // Server tick the input will be applied on, in rollback mode ticks are shared
+                        let target = match (rollback,synced_with) {
+                            (Some(_),_) => Some(sequence as f64),
+                            (None,Some((tick,ack))) => Some(tick as f64 + sequence as f64 - ack as f64),
+                            (None,None) => None,
+                        };
+                        speed = target.map_or(1.0,|target|clock.speed(target));
                     },
                     InputTypeEvent::CharacterSwitch(id) => {
-                        let packet = CharacterSwitchRequest::new(password.clone(),name.clone(),id).to_string();
+                        let packet = CharacterSwitchRequest::new(token.clone(),id).to_string();
                         let response = reqwest::blocking::Client::new()
                             .put(format!("http://{addres}/character/"))
                             .body(packet)
-                            .send().unwrap();
-                        let body = response.text().unwrap();
-                        if let Ok(map) = serde_json::from_str::<Map>(&body) {map_trans.send(map).unwrap();}
+                            .send().and_then(|response|Ok((response.status(),response.bytes()?)));
+                        let error = match response {
+                            Ok((status,body)) if status.is_success() => match Map::from_bytes(&body) {
+                                Some(map) => {let _ = map_trans.send(map); None},
+                                // Answer that cant be read is shown the same as an error from the server
+                                None => Some(ErrorResponse::new(ErrorCode::ParseError).message),
+                            },
+                            // Session expired, it is resumed or joined again
+                            Ok((reqwest::StatusCode::UNAUTHORIZED,_)) | Err(_) => {reconnect = Some(Reconnect::new()); None},
+                            Ok((status,body)) => Some(failure(status,&String::from_utf8_lossy(&body))),
+                        };
+                        if let Some(error) = error {
+                            chat.push(format!("Switching character failed: {error}"));
+                            chat_changed = true;
+                        }
+                    },
+                    InputTypeEvent::Reconnecting => {},
+                    InputTypeEvent::Resume => {
+                        let packet = ResumeRequest::new(token.clone()).to_string();
+                        let status = reqwest::blocking::Client::new()
+                            .post(format!("http://{addres}/resume/"))
+                            .body(packet)
+                            .send().map(|response|response.status());
+                        match status {
+                            Ok(status) if status.is_success() => {
+                                reconnect = Option::None;
+                                last_received = std::time::Instant::now();
+                            },
+                            // Session expired, joining again right away
+                            Ok(reqwest::StatusCode::UNAUTHORIZED) => token.clear(),
+                            _ => if let Some(retry) = &mut reconnect {retry.failed();},
+                        }
                     },
                     InputTypeEvent::Join => {
-                        let packet = JoinRequest::new(password.clone(),name.clone()).to_string();
+                        let (path,packet) = match spectate {
+                            Some(delay) => ("spectate",SpectateRequest::new(password.clone(),name.clone(),room,delay,manifest.clone()).to_string()),
+                            None => ("map",JoinRequest::new(password.clone(),name.clone(),room,manifest.clone()).to_string()),
+                        };
                         let response = reqwest::blocking::Client::new()
-                            .post(format!("http://{addres}/map/"))
+                            .post(format!("http://{addres}/{path}/"))
+                            .body(packet)
+                            .send()
+                            .map(|response|(response.status(),response.text().unwrap_or_default()));
+                        // Server that isnt reachable is the same as one that cant answer now
+                        let (status,body) = response.unwrap_or((reqwest::StatusCode::SERVICE_UNAVAILABLE,String::new()));
+                        if status == reqwest::StatusCode::CONFLICT {
+                            let Some(mismatch) = AssetMismatch::from_string(&body) else {
+                                break Err(format!("Joining the room failed: {}",failure(status,&body)));
+                            };
+                            if !downloaded && mismatch.protocol == PROTOCOL_VERSION && download_missing(&addres,&mismatch,&manifest,&assets) {
+                                downloaded = true;
+                                manifest = AssetManifest::new(&Character::load_all(None,&assets),&MapInformation::load_all(None,&assets),&assets);
+                                prediction = Prediction::new(&assets,1.0/refresh_rate,rollback);
+                                input_type = InputTypeEvent::Join;
+                                continue;
+                            }
+                            break Err(format!("Server doesnt match this client: {}",mismatch.message()));
+                        }
+                        if status.is_server_error() {
+                            reconnect.get_or_insert_with(Reconnect::new).failed();
+                        } else if !status.is_success() {
+                            break Err(format!("Joining the room failed: {}",failure(status,&body)));
+                        } else if let Some(join) = JoinResponse::from_string(&body) {
+                            token = join.token;
+                            // Joined again after the session expired, the server could have restarted
+                            if reconnect.take().is_some() {
+                                prediction = Prediction::new(&assets,1.0/refresh_rate,rollback);
+                                clock = ClockSync::new();
+                                (last_snapshot,last_event,synced_with) = (0,Option::None,Option::None);
+                                last_received = std::time::Instant::now();
+                            }
+                            chat.lines.clear();
+                            for message in join.chat {
+                                chat.push(format!("{}: {}",message.name,message.text));
+                            }
+                            chat_changed = true;
+                        }
+                    },
+                    InputTypeEvent::Quit => {
+                        let packet = LeaveRequest::new(token.clone()).to_string();
+                        let _ = reqwest::blocking::Client::new()
+                            .delete(format!("http://{addres}/map/"))
                             .body(packet)
-                            .send().unwrap();
-                        let body = response.text().unwrap();
-                        if let Ok(map) = serde_json::from_str::<Map>(&body) {map_trans.send(map).unwrap();}
+                            .send();
+                        // Dropping the map channel closes the window too
+                        break Ok(());
                     },
-                    InputTypeEvent::Quit => panic!("Create better quit system"),
                 }
                 input_type = InputTypeEvent::Normal;
+
+                // Only the newest snapshot is rendered, older ones that came late are dropped.
+                let mut newest = Option::None;
+                while let Some((packet,from)) = Packet::receive(&socket) {
+                    if from != server {
+                        continue;
+                    }
+                    last_received = std::time::Instant::now();
+                    match packet.kind {
+                        // Paused room sends the same tick again, it can still carry new events
+                        PacketKind::Snapshot => if packet.sequence >= last_snapshot {
+                            last_snapshot = packet.sequence;
+                            newest = Some(packet);
+                        },
+                        PacketKind::Inputs => if let Some(tick_input) = TickInput::from_bytes(&packet.payload) {
+                            prediction.remote_input(tick_input);
+                        },
+                        PacketKind::Pong => if let Some(response) = TimeResponse::from_bytes(&packet.payload) {
+                            clock.pong(response);
+                            // Admin changed the tick rate, local ticks have to follow
+                            if let Some(rate) = clock.rate() && rate as f32 != refresh_rate {
+                                refresh_rate = rate as f32;
+                                frame_time = std::time::Duration::from_secs_f32(1.0/refresh_rate);
+                                prediction.set_delta(1.0/refresh_rate);
+                            }
+                        },
+                        PacketKind::Input | PacketKind::Watch | PacketKind::Probe | PacketKind::Info | PacketKind::Ping => {},
+                    }
+                }
+                if let Some(packet) = newest && let Some((instance,map)) = read_snapshot(&packet.payload) {
+                    // Chat messages and notifications (joins, leaves, kicks, pauses) share the overlay
+                    for (id,event) in &map.events {
+                        if let Some(last) = last_event && *id > last {
+                            chat.push(event.message());
+                            chat_changed = true;
+                        }
+                    }
+                    if let Some((id,_)) = map.events.last() {
+                        last_event = Some(last_event.map_or(*id,|last|last.max(*id)));
+                    } else if last_event.is_none() {
+                        last_event = Some(0);
+                    }
+                    // Nothing was applied yet, there is nothing to compare with
+                    if packet.ack > 0 {
+                        synced_with = Some((packet.sequence,packet.ack));
+                    }
+                    prediction.reconcile(map,instance,packet.ack,packet.sequence);
+                }
+                // Nothing came for too long, the connection is lost. Delayed spectators get nothing
+                // before the first snapshot, so until then the connection isnt checked.
+                if reconnect.is_none() && last_event.is_some() && last_received.elapsed() > Reconnect::TIMEOUT {
+                    reconnect = Some(Reconnect::new());
+                }
+                let status = reconnect.as_ref().map(Reconnect::status);
+                if chat.status != status {
+                    chat.status = status;
+                    chat_changed = true;
+                }
+                if chat_changed {
+                    let _ = chat_trans.send(chat.clone());
+                }
+                prediction.tick();
+                // Window was closed
+                if let Some(map) = prediction.map() && map_trans.send(map.clone()).is_err() {
+                    input_type = InputTypeEvent::Quit;
+                }
                 
-                let wait = if let Some(wait) = frame_time.checked_sub(std::time::Instant::now() - next_frame) {
+                frame += 1;
+                let wait = if let Some(wait) = frame_time.div_f64(speed).checked_sub(std::time::Instant::now() - next_frame) {
                     wait
                 } else {
                     std::time::Duration::ZERO
@@ -226,9 +587,81 @@ impl Client {
             }
         });
 
+        self.open_window(map_rec,chat_rec,input_trans);
+        // Waits for the leave request to be send
+        network.join().unwrap_or(Ok(()))
+    }
+    /// Plays recorded match instead of connecting to a server, it runs at the speed it was
+    /// recorded with.
+    ///
+    /// Left / Right -> seek 5 seconds, Up / Down -> faster / slower, Space -> pause, Home -> start
+    pub fn replay(&mut self, path: String) -> Result<(),String> {
+        self.render.assets = self.assets.clone();
+        // Characters cant be send to another thread, only the file is read here
+        let (header,frames) = Replay::read(&path)?;
+        let assets = self.assets.clone();
+
+        let (map_trans, map_rec) = mpsc::channel::<Map>();
+        let (input_trans, input_rec) = mpsc::channel::<winit::event::WindowEvent>();
+        let (chat_trans, chat_rec) = mpsc::channel::<ChatOverlay>();
+
+        let _player = thread::spawn(move || {
+            let mut replay = Replay::new(header,frames,&assets);
+            const SEEK_SECONDS: f32 = 5.0;
+            let frame_time = std::time::Duration::from_secs_f32(replay.header.delta);
+            let seek_step = (SEEK_SECONDS/replay.header.delta) as usize;
+            let mut speed: f32 = 1.0;
+            let mut paused = false;
+            // Ticks that should be played but werent yet, allows speeds slower then 1.
+            let mut progress: f32 = 0.0;
+            let mut desync_shown = false;
+            replay.step();
+            loop {
+                let next_frame = std::time::Instant::now();
+                for key_input in input_rec.try_iter() {
+                    if let WindowEvent::KeyboardInput{event, ..} = key_input &&
+                        event.state == ElementState::Pressed &&
+                        let winit::keyboard::PhysicalKey::Code(key) = event.physical_key {
+                            let tick = replay.map().counter;
+                            match key {
+                                KeyCode::ArrowLeft => replay.seek(tick.saturating_sub(seek_step)),
+                                KeyCode::ArrowRight => replay.seek(tick + seek_step),
+                                KeyCode::ArrowUp => speed = (speed*2.0).min(16.0),
+                                KeyCode::ArrowDown => speed = (speed/2.0).max(1.0/16.0),
+                                KeyCode::Space => paused = !paused,
+                                KeyCode::Home => replay.seek(replay.first_tick()),
+                                _ => {},
+                            }
+                    }
+                }
+                if !paused && !replay.finished() {
+                    progress += speed;
+                    while progress >= 1.0 {
+                        progress -= 1.0;
+                        replay.step();
+                    }
+                }
+                if let Some(tick) = replay.desynced() && !desync_shown {
+                    desync_shown = true;
+                    let status = Some(format!("Replay desynced at tick {tick}"));
+                    let _ = chat_trans.send(ChatOverlay {status, ..ChatOverlay::default()});
+                }
+                if map_trans.send(replay.map().clone()).is_err() {
+                    break;
+                }
+                let wait = frame_time.checked_sub(std::time::Instant::now() - next_frame).unwrap_or(std::time::Duration::ZERO);
+                thread::sleep(wait);
+            }
+        });
+
+        self.open_window(map_rec,chat_rec,input_trans);
+        Ok(())
+    }
+    /// Opens a window that renders every map send to it, runs untill the window is closed.
+    fn open_window(&self, map_rec: mpsc::Receiver<Map>, chat_rec: mpsc::Receiver<ChatOverlay>, input_trans: mpsc::Sender<WindowEvent>) {
         let event_loop = EventLoop::builder().build().expect("event loop building");
         let (window, display) = SimpleWindowBuilder::new().build(&event_loop);
-        let mut game_renderer = GameRanderer::new(map_rec,input_trans, window, display,&self.render);
+        let mut game_renderer = GameRanderer::new(map_rec,chat_rec,input_trans, window, display,&self.render);
         let _ = event_loop.run_app(&mut game_renderer);
     }
 }
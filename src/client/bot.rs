@@ -0,0 +1,333 @@
+use std::{
+    collections::VecDeque,
+    fmt::Write,
+    thread,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+use crate::base::random::Random;
+use crate::client::Client;
+use crate::game::{
+    clock::*,
+    manifest::AssetManifest,
+    networking::*,
+    physic::Direction,
+    room::{
+        Room,
+        RoomInfo,
+    },
+    simulator::NetworkSimulator,
+    transport::*,
+};
+
+/// Latencies and failures of one kind of request.
+#[derive(Default, Clone)]
+pub struct Measurement {
+    samples: Vec<Duration>,
+    pub errors: usize,
+}
+impl Measurement {
+    fn sample(&mut self, latency: Duration) {
+        self.samples.push(latency);
+    }
+    fn error(&mut self) {
+        self.errors += 1;
+    }
+    fn merge(&mut self, other: Measurement) {
+        self.samples.extend(other.samples);
+        self.errors += other.errors;
+    }
+    /// Successful and failed requests together.
+    pub fn count(&self) -> usize {
+        self.samples.len() + self.errors
+    }
+    /// Part of requests that failed, 0 to 1.
+    pub fn error_rate(&self) -> f64 {
+        if self.count() == 0 {
+            return 0.0;
+        }
+        self.errors as f64/self.count() as f64
+    }
+    /// Latency that this part (0 to 1) of successful requests was faster then.
+    pub fn percentile(&self, part: f64) -> Option<Duration> {
+        let mut sorted = self.samples.clone();
+        sorted.sort();
+        let index = ((sorted.len() as f64*part).ceil() as usize).clamp(1,sorted.len().max(1)) - 1;
+        sorted.get(index).copied()
+    }
+}
+/// Measurements of all bots of a load test together.
+#[derive(Default)]
+pub struct LoadReport {
+    pub bots: usize,
+    /// Bots that joined and played until the end.
+    pub finished: usize,
+    /// Http join of the room.
+    pub join: Measurement,
+    /// Http character switches.
+    pub switch: Measurement,
+    /// From sending input to a snapshot acknowledging it, unacknowledged inputs are errors.
+    pub input: Measurement,
+    /// Round trip of ping and pong packets, unanswered pings are errors.
+    pub ping: Measurement,
+    /// Time between snapshots, every time they stop coming is an error.
+    pub snapshot: Measurement,
+    /// Http leave at the end.
+    pub leave: Measurement,
+}
+impl LoadReport {
+    pub fn merge(&mut self, other: LoadReport) {
+        self.bots += other.bots;
+        self.finished += other.finished;
+        self.join.merge(other.join);
+        self.switch.merge(other.switch);
+        self.input.merge(other.input);
+        self.ping.merge(other.ping);
+        self.snapshot.merge(other.snapshot);
+        self.leave.merge(other.leave);
+    }
+    /// Table with percentiles and error rates of everything measured.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out,"{} bots, {} played until the end",self.bots,self.finished);
+        let _ = writeln!(out,"{:<10}{:>9}{:>8}{:>8}{:>10}{:>10}{:>10}{:>10}","","count","errors","rate","p50 ms","p90 ms","p99 ms","max ms");
+        let milliseconds = |latency: Option<Duration>| latency.map_or(String::from("-"),|latency|format!("{:.1}",latency.as_secs_f64()*1000.0));
+        for (name,measurement) in [
+            ("join",&self.join),
+            ("switch",&self.switch),
+            ("input",&self.input),
+            ("ping",&self.ping),
+            ("snapshot",&self.snapshot),
+            ("leave",&self.leave),
+        ] {
+            let _ = writeln!(out,"{name:<10}{:>9}{:>8}{:>7.2}%{:>10}{:>10}{:>10}{:>10}",
+                measurement.count(),
+                measurement.errors,
+                measurement.error_rate()*100.0,
+                milliseconds(measurement.percentile(0.5)),
+                milliseconds(measurement.percentile(0.9)),
+                milliseconds(measurement.percentile(0.99)),
+                milliseconds(measurement.percentile(1.0)),
+            );
+        }
+        out
+    }
+}
+/// Client without a window that joins a room, plays random inputs and measures how the server
+/// answers. Bots play like normal clients, rollback mode is not used.
+pub struct Bot {
+    name: String,
+    password: String,
+    room: u32,
+    addres: String,
+    /// Shared by all bots, building one takes long enough to delay hundreds of bots.
+    http: reqwest::blocking::Client,
+    refresh_rate: f32,
+    network: crate::game::simulator::NetworkConditions,
+    manifest: AssetManifest,
+    /// Characters the bot switches between.
+    characters: Vec<u32>,
+    duration: Duration,
+    random: Random,
+}
+impl Bot {
+    /// How many inputs are send in one packet, same as normal clients.
+    const INPUT_REDUNDANCY: usize = 8;
+    /// Ticks between pings of the server.
+    const PING_INTERVAL: u32 = 30;
+    /// Most ticks one random input is held for.
+    const MAX_HOLD: u64 = 20;
+    /// Average seconds between character switches.
+    const SWITCH_SECONDS: f32 = 10.0;
+    /// Input or ping without answer for this long is lost, same for the time without snapshots.
+    const LOST_AFTER: Duration = Duration::from_secs(2);
+    /// Http requests that take longer fail.
+    pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+    /// Bot playing for duration in the room of its seat (room id and password) on the server of
+    /// the client.
+    pub fn new(client: &Client, http: reqwest::blocking::Client, name: String, seat: (u32,String), manifest: AssetManifest, characters: Vec<u32>, duration: Duration) -> Bot {
+        let (room,password) = seat;
+        // Bots started at the same time still get different inputs
+        let seed = name.bytes().fold(Random::new().next_u64(),|seed,byte|seed.rotate_left(8) ^ byte as u64);
+        Bot {
+            name,
+            password,
+            room,
+            addres: client.addres.clone(),
+            http,
+            refresh_rate: client.refresh_rate,
+            network: client.network,
+            manifest,
+            characters,
+            duration,
+            random: Random::seeded(seed),
+        }
+    }
+    /// Sends http request and measures it until the whole body is read, unsuccessful status is
+    /// an error.
+    fn request(measurement: &mut Measurement, request: reqwest::blocking::RequestBuilder) -> Option<Vec<u8>> {
+        let start = Instant::now();
+        let body = request.send().ok()
+            .filter(|response|response.status().is_success())
+            .and_then(|response|response.bytes().ok());
+        match body {
+            Some(body) => {
+                measurement.sample(start.elapsed());
+                Some(body.to_vec())
+            },
+            None => {
+                measurement.error();
+                None
+            },
+        }
+    }
+    fn random_input(&mut self) -> CharacterInput {
+        CharacterInput {
+            dir: match self.random.below(3) {
+                0 => None,
+                1 => Some(Direction::Left),
+                _ => Some(Direction::Right),
+            },
+            light_attack: self.random.chance(0.2),
+            heavy_attack: self.random.chance(0.1),
+            special: self.random.chance(0.05),
+            jump: self.random.chance(0.2),
+            down: self.random.chance(0.1),
+        }
+    }
+    /// Plays until the duration runs out and leaves, returns what was measured.
+    pub fn run(mut self) -> LoadReport {
+        let mut report = LoadReport {
+            bots: 1,
+            ..Default::default()
+        };
+        let http = self.http.clone();
+        let addres = self.addres.clone();
+
+        let packet = JoinRequest::new(self.password.clone(),self.name.clone(),self.room,self.manifest.clone()).to_string();
+        let Some(token) = Self::request(&mut report.join,http.post(format!("http://{addres}/map/")).body(packet))
+            .and_then(|body|JoinResponse::from_string(&String::from_utf8_lossy(&body)))
+            .map(|join|join.token) else {
+                return report;
+            };
+        let Some((socket,server)) = client_socket(&addres) else {
+            return report;
+        };
+        socket.set_nonblocking(true).expect("Setting UDP socket to non blocking failed");
+        let simulator = NetworkSimulator::new(self.network,&socket);
+
+        let frame_time = Duration::from_secs_f32(1.0/self.refresh_rate);
+        let switch_chance = 1.0/(Self::SWITCH_SECONDS*self.refresh_rate);
+        let start = Instant::now();
+        let mut switch = true;
+        let mut input = CharacterInput::new();
+        let mut hold: u64 = 0;
+        let mut sequence: u32 = 0;
+        let mut sent: VecDeque<CharacterInput> = VecDeque::new();
+        // Inputs and pings waiting for an answer with the time they were send
+        let mut pending: VecDeque<(u32,Instant)> = VecDeque::new();
+        let mut pings: VecDeque<(u64,Instant)> = VecDeque::new();
+        let mut last_snapshot: u32 = 0;
+        let mut last_snapshot_time = Instant::now();
+        let mut stalled = false;
+        while start.elapsed() < self.duration {
+            let next_frame = Instant::now();
+            if switch || self.random.chance(switch_chance) {
+                switch = false;
+                let character = self.characters.get(self.random.below(self.characters.len() as u64) as usize).copied();
+                let packet = CharacterSwitchRequest::new(token.clone(),character).to_string();
+                Self::request(&mut report.switch,http.put(format!("http://{addres}/character/")).body(packet));
+            }
+            if hold == 0 {
+                input = self.random_input();
+                hold = self.random.below(Self::MAX_HOLD) + 1;
+            }
+            hold -= 1;
+
+            sequence += 1;
+            sent.push_back(input.clone());
+            if sent.len() > Self::INPUT_REDUNDANCY {
+                sent.pop_front();
+            }
+            pending.push_back((sequence,Instant::now()));
+            let packet = GameControlPacket::new(token.clone(),sent.iter().cloned().collect()).as_bytes();
+            Packet::new(PacketKind::Input,sequence,last_snapshot,packet).send_through(&socket,simulator.as_ref(),&server);
+            if sequence.is_multiple_of(Self::PING_INTERVAL) {
+                let client_time = start.elapsed().as_micros() as u64;
+                pings.push_back((client_time,Instant::now()));
+                let packet = TimeRequest {token: token.clone(),client_time}.as_bytes();
+                Packet::new(PacketKind::Ping,sequence,last_snapshot,packet).send_through(&socket,simulator.as_ref(),&server);
+            }
+
+            while let Some((packet,from)) = Packet::receive(&socket) {
+                if from != server {
+                    continue;
+                }
+                match packet.kind {
+                    PacketKind::Snapshot => if packet.sequence >= last_snapshot {
+                        last_snapshot = packet.sequence;
+                        report.snapshot.sample(last_snapshot_time.elapsed());
+                        last_snapshot_time = Instant::now();
+                        stalled = false;
+                        while let Some((input,send)) = pending.front() && *input <= packet.ack {
+                            report.input.sample(send.elapsed());
+                            pending.pop_front();
+                        }
+                    },
+                    PacketKind::Pong => if let Some(response) = TimeResponse::from_bytes(&packet.payload) &&
+                        let Some(index) = pings.iter().position(|(time,_)|*time == response.client_time) &&
+                        let Some((_,send)) = pings.remove(index) {
+                            report.ping.sample(send.elapsed());
+                    },
+                    PacketKind::Input | PacketKind::Inputs | PacketKind::Watch | PacketKind::Probe | PacketKind::Info | PacketKind::Ping => {},
+                }
+            }
+            while let Some((_,send)) = pending.front() && send.elapsed() > Self::LOST_AFTER {
+                report.input.error();
+                pending.pop_front();
+            }
+            while let Some((_,send)) = pings.front() && send.elapsed() > Self::LOST_AFTER {
+                report.ping.error();
+                pings.pop_front();
+            }
+            if !stalled && last_snapshot_time.elapsed() > Self::LOST_AFTER {
+                report.snapshot.error();
+                stalled = true;
+            }
+
+            let wait = frame_time.checked_sub(next_frame.elapsed()).unwrap_or(Duration::ZERO);
+            thread::sleep(wait);
+        }
+        let packet = LeaveRequest::new(token).to_string();
+        if Self::request(&mut report.leave,http.delete(format!("http://{addres}/map/")).body(packet)).is_some() {
+            report.finished = 1;
+        }
+        report
+    }
+}
+/// Rooms and theyr passwords for every bot. The room of the client is filled first, when it is
+/// full new rooms are created with the client password (has to be the server password).
+pub fn seats(client: &Client, bots: usize) -> Vec<(u32,String)> {
+    let info = client.list_rooms()
+        .and_then(|rooms|rooms.into_iter().find(|room|room.id == client.room));
+    let free = info.as_ref().map_or(Room::MAX_PLAYERS,|info|info.max_players.saturating_sub(info.players));
+    let map_id = info.as_ref().map_or(1,|info|info.map_id);
+    let mut seats = vec![(client.room,client.password.clone());free.min(bots)];
+    while seats.len() < bots {
+        let packet = CreateRoomRequest::new(client.password.clone(),format!("{} load test",client.name),String::new(),map_id).to_string();
+        let created = reqwest::blocking::Client::new()
+            .post(format!("http://{0}/rooms/",client.addres))
+            .body(packet)
+            .send().ok()
+            .and_then(|response|response.text().ok())
+            .and_then(|body|serde_json::from_str::<RoomInfo>(&body).ok());
+        match created {
+            Some(room) => seats.extend(vec![(room.id,String::new());room.max_players.min(bots - seats.len())]),
+            // Remaining bots try the room of the client, theyr failed joins show in the report
+            None => seats.resize(bots,(client.room,client.password.clone())),
+        }
+    }
+    seats
+}
@@ -0,0 +1,93 @@
+use std::{
+    collections::VecDeque,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+use crate::game::clock::*;
+
+/// NTP style estimate of the server tick. Every pong gives round trip time and offset between
+/// the local clock and the server tick, the sample with the shortest round trip is trusted most.
+pub struct ClockSync {
+    start: Instant,
+    /// Last samples, (round trip in seconds, server tick at local time 0, server ticks per second).
+    samples: VecDeque<(f64,f64,f64)>,
+}
+impl Default for ClockSync {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl ClockSync {
+    /// How many samples are kept, older ones can be from a different network situation.
+    const SAMPLES: usize = 8;
+    /// Inputs should come this many ticks before they are needed, covers jitter.
+    const MARGIN: f64 = 1.0;
+    /// How much is the tick speed changed for every tick of error.
+    const GAIN: f64 = 0.02;
+    /// Most the tick speed can differ from the normal one.
+    const MAX_ADJUST: f64 = 0.1;
+    pub fn new() -> ClockSync {
+        ClockSync {
+            start: Instant::now(),
+            samples: VecDeque::new(),
+        }
+    }
+    /// Seconds since the start of the local clock.
+    fn now(&self) -> f64 {
+        self.start.elapsed().as_secs_f64()
+    }
+    /// Payload of the next ping.
+    pub fn ping(&self, token: String) -> TimeRequest {
+        TimeRequest {
+            token,
+            client_time: self.start.elapsed().as_micros() as u64,
+        }
+    }
+    /// Takes answer of the server, server time is assumed to be in the middle of the round trip.
+    pub fn pong(&mut self, response: TimeResponse) {
+        let send = response.client_time as f64/1_000_000.0;
+        let round_trip = self.now() - send;
+        if round_trip < 0.0 || response.rate <= 0.0 {
+            return;
+        }
+        let rate = response.rate as f64;
+        // Tick rate of the server changed, old samples count ticks differently
+        if self.rate().is_some_and(|old|old != rate) {
+            self.samples.clear();
+        }
+        let offset = response.server_tick() - (send + round_trip/2.0)*rate;
+        self.samples.push_back((round_trip,offset,rate));
+        if self.samples.len() > Self::SAMPLES {
+            self.samples.pop_front();
+        }
+    }
+    /// Sample with the shortest round trip, its time was the least delayed.
+    fn best(&self) -> Option<(f64,f64,f64)> {
+        self.samples.iter().copied().min_by(|a,b|a.0.total_cmp(&b.0))
+    }
+    /// Ticks per second the server runs at, from the newest pong.
+    pub fn rate(&self) -> Option<f64> {
+        self.samples.back().map(|(_,_,rate)|*rate)
+    }
+    pub fn round_trip(&self) -> Option<Duration> {
+        self.best().map(|(round_trip,_,_)|Duration::from_secs_f64(round_trip))
+    }
+    /// Estimated tick of the server right now.
+    pub fn server_tick(&self) -> Option<f64> {
+        let (_,offset,rate) = self.best()?;
+        Some(self.now()*rate + offset)
+    }
+    /// Speed the local tick should run at so input send now arrives just before the server tick
+    /// it is for, 1.0 when synchronised or when nothing is known yet.
+    pub fn speed(&self, target_tick: f64) -> f64 {
+        let (Some((round_trip,_,rate)),Some(server_tick)) = (self.best(),self.server_tick()) else {
+            return 1.0;
+        };
+        let arrival = server_tick + round_trip/2.0*rate;
+        // Positive when inputs come too early and wait on the server
+        let error = target_tick - arrival - Self::MARGIN;
+        1.0 - (error*Self::GAIN).clamp(-Self::MAX_ADJUST,Self::MAX_ADJUST)
+    }
+}
@@ -0,0 +1,145 @@
+use glium::{
+    glutin::surface::WindowSurface,
+    Surface,
+    Display,
+};
+
+/// Chat drawn over the game, last messages and the text that is being written.
+#[derive(Clone, Default)]
+pub struct ChatOverlay {
+    pub lines: Vec<String>,
+    /// Some while the chat input is open.
+    pub typing: Option<String>,
+    /// State of the connection shown above the chat, None while connected.
+    pub status: Option<String>,
+}
+impl ChatOverlay {
+    /// How many last messages are shown.
+    const LINES: usize = 6;
+    /// Adds message, only the last few are kept.
+    pub fn push(&mut self, line: String) {
+        self.lines.push(line);
+        if self.lines.len() > Self::LINES {
+            self.lines.remove(0);
+        }
+    }
+    /// Size of one font pixel in screen pixels.
+    const SCALE: f32 = 2.0;
+    /// Distance from the top left corner of the window in screen pixels.
+    const MARGIN: f32 = 10.0;
+    /// Glyphs 5x7, every row is one byte with the left pixel in bit 4. Characters from ' ' to '`'
+    /// followed by '{' to '~', lower case letters are drawn as upper case.
+    const FONT: [[u8;7];69] = [
+        [0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x04,0x04,0x04,0x04,0x04,0x00,0x04],
+        [0x0A,0x0A,0x0A,0x00,0x00,0x00,0x00], [0x0A,0x0A,0x1F,0x0A,0x1F,0x0A,0x0A],
+        [0x04,0x0F,0x14,0x0E,0x05,0x1E,0x04], [0x18,0x19,0x02,0x04,0x08,0x13,0x03],
+        [0x0C,0x12,0x14,0x08,0x15,0x12,0x0D], [0x0C,0x04,0x08,0x00,0x00,0x00,0x00],
+        [0x02,0x04,0x08,0x08,0x08,0x04,0x02], [0x08,0x04,0x02,0x02,0x02,0x04,0x08],
+        [0x00,0x04,0x15,0x0E,0x15,0x04,0x00], [0x00,0x04,0x04,0x1F,0x04,0x04,0x00],
+        [0x00,0x00,0x00,0x00,0x0C,0x04,0x08], [0x00,0x00,0x00,0x1F,0x00,0x00,0x00],
+        [0x00,0x00,0x00,0x00,0x00,0x0C,0x0C], [0x00,0x01,0x02,0x04,0x08,0x10,0x00],
+        [0x0E,0x11,0x13,0x15,0x19,0x11,0x0E], [0x04,0x0C,0x04,0x04,0x04,0x04,0x0E],
+        [0x0E,0x11,0x01,0x02,0x04,0x08,0x1F], [0x1F,0x02,0x04,0x02,0x01,0x11,0x0E],
+        [0x02,0x06,0x0A,0x12,0x1F,0x02,0x02], [0x1F,0x10,0x1E,0x01,0x01,0x11,0x0E],
+        [0x06,0x08,0x10,0x1E,0x11,0x11,0x0E], [0x1F,0x01,0x02,0x04,0x08,0x08,0x08],
+        [0x0E,0x11,0x11,0x0E,0x11,0x11,0x0E], [0x0E,0x11,0x11,0x0F,0x01,0x02,0x0C],
+        [0x00,0x0C,0x0C,0x00,0x0C,0x0C,0x00], [0x00,0x0C,0x0C,0x00,0x0C,0x04,0x08],
+        [0x02,0x04,0x08,0x10,0x08,0x04,0x02], [0x00,0x00,0x1F,0x00,0x1F,0x00,0x00],
+        [0x08,0x04,0x02,0x01,0x02,0x04,0x08], [0x0E,0x11,0x01,0x02,0x04,0x00,0x04],
+        [0x0E,0x11,0x01,0x0D,0x15,0x15,0x0E], [0x0E,0x11,0x11,0x11,0x1F,0x11,0x11],
+        [0x1E,0x11,0x11,0x1E,0x11,0x11,0x1E], [0x0E,0x11,0x10,0x10,0x10,0x11,0x0E],
+        [0x1C,0x12,0x11,0x11,0x11,0x12,0x1C], [0x1F,0x10,0x10,0x1E,0x10,0x10,0x1F],
+        [0x1F,0x10,0x10,0x1E,0x10,0x10,0x10], [0x0E,0x11,0x10,0x17,0x11,0x11,0x0F],
+        [0x11,0x11,0x11,0x1F,0x11,0x11,0x11], [0x0E,0x04,0x04,0x04,0x04,0x04,0x0E],
+        [0x07,0x02,0x02,0x02,0x02,0x12,0x0C], [0x11,0x12,0x14,0x18,0x14,0x12,0x11],
+        [0x10,0x10,0x10,0x10,0x10,0x10,0x1F], [0x11,0x1B,0x15,0x15,0x11,0x11,0x11],
+        [0x11,0x11,0x19,0x15,0x13,0x11,0x11], [0x0E,0x11,0x11,0x11,0x11,0x11,0x0E],
+        [0x1E,0x11,0x11,0x1E,0x10,0x10,0x10], [0x0E,0x11,0x11,0x11,0x15,0x12,0x0D],
+        [0x1E,0x11,0x11,0x1E,0x14,0x12,0x11], [0x0F,0x10,0x10,0x0E,0x01,0x01,0x1E],
+        [0x1F,0x04,0x04,0x04,0x04,0x04,0x04], [0x11,0x11,0x11,0x11,0x11,0x11,0x0E],
+        [0x11,0x11,0x11,0x11,0x11,0x0A,0x04], [0x11,0x11,0x11,0x15,0x15,0x15,0x0A],
+        [0x11,0x11,0x0A,0x04,0x0A,0x11,0x11], [0x11,0x11,0x11,0x0A,0x04,0x04,0x04],
+        [0x1F,0x01,0x02,0x04,0x08,0x10,0x1F], [0x0E,0x08,0x08,0x08,0x08,0x08,0x0E],
+        [0x00,0x10,0x08,0x04,0x02,0x01,0x00], [0x0E,0x02,0x02,0x02,0x02,0x02,0x0E],
+        [0x04,0x0A,0x11,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x1F],
+        [0x08,0x04,0x02,0x00,0x00,0x00,0x00], [0x02,0x04,0x04,0x08,0x04,0x04,0x02],
+        [0x04,0x04,0x04,0x04,0x04,0x04,0x04], [0x08,0x04,0x04,0x02,0x04,0x04,0x08],
+        [0x00,0x00,0x08,0x15,0x02,0x00,0x00],
+    ];
+    /// Glyph of a character, unknown characters are drawn as '?'.
+    fn glyph(character: char) -> &'static [u8;7] {
+        let index = match character.to_ascii_uppercase() {
+            character @ ' '..='`' => character as usize - ' ' as usize,
+            character @ '{'..='~' => character as usize - '{' as usize + 65,
+            _ => '?' as usize - ' ' as usize,
+        };
+        &Self::FONT[index]
+    }
+    /// Triangles of every lit pixel of the text, in screen pixels from the top left corner.
+    fn text_shape(text: &str, start: [f32;2], out: &mut Vec<[f32;2]>) {
+        for (column,character) in text.chars().enumerate() {
+            for (row,bits) in Self::glyph(character).iter().enumerate() {
+                for bit in 0..5 {
+                    if bits & (0x10 >> bit) == 0 {
+                        continue;
+                    }
+                    let x = start[0] + (column*6 + bit) as f32*Self::SCALE;
+                    let y = start[1] + row as f32*Self::SCALE;
+                    let (x2,y2) = (x + Self::SCALE,y + Self::SCALE);
+                    out.extend([[x,y],[x2,y],[x2,y2],[x2,y2],[x,y2],[x,y]]);
+                }
+            }
+        }
+    }
+    /// Draws the chat in to the top left corner, white text with a dark shadow so it can be read
+    /// on any map.
+    pub fn draw(&self, display: &mut Display<WindowSurface>, frame: &mut glium::Frame) {
+        let mut text: Vec<String> = self.status.iter().cloned().chain(self.lines.iter().cloned()).collect();
+        if let Some(typing) = &self.typing {
+            text.push(format!("> {typing}_"));
+        }
+        let mut pixels = Vec::new();
+        for (line,string) in text.iter().enumerate() {
+            Self::text_shape(string,[Self::MARGIN,Self::MARGIN + line as f32*9.0*Self::SCALE],&mut pixels);
+        }
+        if pixels.is_empty() {
+            return;
+        }
+        #[derive(Copy, Clone)]
+        struct Ver {
+            position: [f32;2],
+        }
+        implement_vertex!(Ver, position);
+        let (width,height) = display.get_framebuffer_dimensions();
+        let (width,height) = (width as f32, height as f32);
+        let to_screen = |offset: f32| -> Vec<Ver> {
+            pixels.iter()
+                .map(|[x,y]|Ver {position: [(x + offset)/width*2.0 - 1.0,1.0 - (y + offset)/height*2.0]})
+                .collect()
+        };
+        let fragment_shader_src = r#"
+            #version 140
+            uniform vec4 in_color;
+            out vec4 out_color;
+            void main() {
+                out_color = in_color;
+            }
+        "#;
+        let vertex_shader_src = r#"
+            #version 140
+            in vec2 position;
+            void main() {
+                gl_Position = vec4(position, 0.0, 1.0);
+            }
+        "#;
+        let program = glium::Program::from_source(display, vertex_shader_src, fragment_shader_src, None).unwrap();
+        let indices = glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList);
+        for (offset,color) in [(Self::SCALE/2.0,[0.0,0.0,0.0,1.0f32]),(0.0,[1.0,1.0,1.0,1.0f32])] {
+            let vertex_buffer = glium::VertexBuffer::new(display, &to_screen(offset)).unwrap();
+            let uniforms = uniform! {
+                in_color: color,
+            };
+            frame.draw(&vertex_buffer, indices, &program, &uniforms, &Default::default()).unwrap();
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::{
+    HashMap,
+    VecDeque,
+};
+use crate::game::{
+    character::Character,
+    map::*,
+    networking::CharacterInput,
+    rollback::*,
+};
+
+/// Runs the local character ahead of the server so inputs are seen right away.
+/// Every snapshot from the server replaces the predicted state and inputs that the server didnt
+/// process yet are played again on top of it.
+///
+/// In rollback mode whole map is simulated on the client from inputs of all players instead.
+pub struct Prediction {
+    characters: HashMap<u32,Character>,
+    map_pool: HashMap<usize,MapInformation>,
+    delta: f32,
+
+    /// Sequence of the last input send to the server.
+    sequence: u32,
+    /// Inputs send to the server that werent confirmed by a snapshot yet.
+    pending: VecDeque<(u32,CharacterInput)>,
+    /// Last input confirmed by server, needed to know what buttons were just pressed.
+    confirmed: CharacterInput,
+    /// Inputs of the last few sequences, oldest first, every input packet repeats them.
+    sent: VecDeque<CharacterInput>,
+
+    instance: Option<u32>,
+    map: Option<Map>,
+
+    rollback_settings: Option<RollbackSettings>,
+    rollback: Option<Rollback>,
+}
+impl Prediction {
+    /// Most inputs kept waiting for confirmation, older are forgoten.
+    const MAX_PENDING: usize = 256;
+    /// How many inputs are send in one packet.
+    const INPUT_REDUNDANCY: usize = 8;
+    pub fn new(assets: &String, delta: f32, rollback_settings: Option<RollbackSettings>) -> Prediction {
+        Prediction {
+            characters: Character::load_all(None,assets),
+            map_pool: MapInformation::load_all(None,assets),
+            delta,
+            sequence: 0,
+            pending: VecDeque::new(),
+            confirmed: CharacterInput::new(),
+            sent: VecDeque::new(),
+            instance: None,
+            map: None,
+            rollback_settings,
+            rollback: None,
+        }
+    }
+    /// Server changed its tick rate, following ticks are simulated with the new length.
+    pub fn set_delta(&mut self, delta: f32) {
+        self.delta = delta;
+    }
+    /// Current predicted state of the map.
+    pub fn map(&self) -> Option<&Map> {
+        match &self.rollback {
+            Some(rollback) => Some(rollback.map()),
+            None => self.map.as_ref(),
+        }
+    }
+    /// Inputs that should be send with the last sequence, oldest first.
+    pub fn sent_inputs(&self) -> Vec<CharacterInput> {
+        self.sent.iter().cloned().collect()
+    }
+    /// Remembers input for the next packets, inputs have to follow each other without a gap.
+    fn remember(&mut self, input: &CharacterInput, skipped: bool) {
+        if skipped {
+            self.sent.clear();
+        }
+        self.sent.push_back(input.clone());
+        if self.sent.len() > Self::INPUT_REDUNDANCY {
+            self.sent.pop_front();
+        }
+    }
+    /// Takes a new input that is going to be send to the server and returns its sequence.
+    /// In rollback mode the sequence is the tick the input will be played on.
+    pub fn input(&mut self, input: CharacterInput) -> u32 {
+        if let Some(rollback) = &mut self.rollback {
+            let previous = self.sequence;
+            self.sequence = (self.sequence + 1).max(rollback.input_tick() as u32);
+            if let Some(instance) = self.instance {
+                rollback.add_input(TickInput {instance, tick: self.sequence as usize, input: input.clone()});
+            }
+            self.remember(&input,self.sequence != previous + 1);
+            return self.sequence;
+        }
+        self.sequence += 1;
+        self.remember(&input,false);
+        self.pending.push_back((self.sequence,input.clone()));
+        if self.pending.len() > Self::MAX_PENDING {
+            self.pending.pop_front();
+        }
+        self.step(input);
+        self.sequence
+    }
+    /// Input of another player relayed by the server in rollback mode.
+    pub fn remote_input(&mut self, input: TickInput) {
+        if let Some(rollback) = &mut self.rollback && Some(input.instance) != self.instance {
+            rollback.add_input(input);
+        }
+    }
+    /// Simulates one tick in rollback mode, waits if other players are too far behind.
+    pub fn tick(&mut self) {
+        if let Some(rollback) = &mut self.rollback && !rollback.waiting() {
+            rollback.advance(&self.characters,&self.map_pool,&self.delta);
+        }
+    }
+    /// Replaces prediction with authoritative map and replays inputs newer then ack.
+    /// In rollback mode the map is used to correct the saved states and simulation is caught up
+    /// to the server tick.
+    pub fn reconcile(&mut self, map: Map, instance: Option<u32>, ack: u32, server_tick: u32) {
+        self.instance = instance;
+        if let Some(settings) = self.rollback_settings {
+            let rollback = self.rollback.get_or_insert_with(||Rollback::new(map.clone(),settings));
+            rollback.resync(map);
+            while rollback.tick() < server_tick as usize {
+                rollback.advance(&self.characters,&self.map_pool,&self.delta);
+            }
+            return;
+        }
+        while let Some((sequence,_)) = self.pending.front() && *sequence <= ack {
+            if let Some((_,input)) = self.pending.pop_front() {
+                self.confirmed = input;
+            }
+        }
+        self.map = Some(map);
+
+        let confirmed = self.confirmed.clone();
+        if let Some(instance) = self.own_character() {
+            instance.last_input = confirmed;
+        }
+        let inputs: Vec<CharacterInput> = self.pending.iter().map(|(_,input)|input.clone()).collect();
+        for input in inputs {
+            self.step(input);
+        }
+    }
+    fn own_character(&mut self) -> Option<&mut crate::game::character::CharacterInstance> {
+        self.map.as_mut()?.characters.get_mut(&self.instance?)
+    }
+    /// One tick of simulation for the local character only, others wait for the server.
+    fn step(&mut self, input: CharacterInput) {
+        if let Some(map) = &mut self.map &&
+            let Some(map_info) = self.map_pool.get(&map.map_id) &&
+            let Some(id) = self.instance &&
+            let Some(instance) = map.characters.get_mut(&id) &&
+            let Some(sheet) = self.characters.get(&instance.character) {
+                instance.input = input;
+                instance.update(sheet,map_info,&self.delta);
+        }
+    }
+}
@@ -0,0 +1,47 @@
+use std::time::{
+    Duration,
+    Instant,
+};
+
+/// Connection to the server was lost, tries are spaced with exponential backoff so a server that
+/// is restarting isnt flooded.
+pub struct Reconnect {
+    /// Failed tries so far.
+    attempt: u32,
+    next_try: Instant,
+}
+impl Reconnect {
+    /// Nothing received from the server for this long means the connection was lost.
+    pub const TIMEOUT: Duration = Duration::from_secs(3);
+    /// Wait after the first failed try, it doubles with every other one.
+    const FIRST_DELAY: Duration = Duration::from_millis(250);
+    const MAX_DELAY: Duration = Duration::from_secs(8);
+    /// First try is right away.
+    pub fn new() -> Reconnect {
+        Reconnect {
+            attempt: 0,
+            next_try: Instant::now(),
+        }
+    }
+    pub fn ready(&self) -> bool {
+        Instant::now() >= self.next_try
+    }
+    /// Try failed, the next one waits twice as long as the last one.
+    pub fn failed(&mut self) {
+        let delay = Self::FIRST_DELAY.saturating_mul(1 << self.attempt.min(16)).min(Self::MAX_DELAY);
+        self.attempt += 1;
+        self.next_try = Instant::now() + delay;
+    }
+    /// Text shown to the player.
+    pub fn status(&self) -> String {
+        match self.attempt {
+            0 => String::from("Reconnecting..."),
+            attempt => format!("Reconnecting... (attempt {})",attempt + 1),
+        }
+    }
+}
+impl Default for Reconnect {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -3,7 +3,7 @@ use winit::application::ApplicationHandler;
 use std::collections::HashMap;
 use crate::game::physic::Direction;
 
-use crate::game::character::Character;
+use crate::game::character::{Character,CharacterInstance};
 use crate::game::map::MapInformation;
 
 use image::ImageReader;
@@ -19,7 +19,7 @@ use winit::{
         WindowId,
     },
 };
-use std::sync::mpsc::{Receiver,Sender};
+use std::sync::mpsc::{Receiver,Sender,TryRecvError};
 use glium::{
     glutin::surface::WindowSurface,
     Surface,
@@ -27,6 +27,7 @@ use glium::{
 };
 
 use crate::game::map::Map;
+use crate::client::overlay::ChatOverlay;
 
 
 /// Base block for drawing on to screen with textures.
@@ -196,6 +197,9 @@ impl Texture {
 pub struct GameRanderer {
     input_channel: Sender<WindowEvent>,
     map_channel: Receiver<Map>,
+    /// Replays dont have chat, the channel is closed right away.
+    chat_channel: Receiver<ChatOverlay>,
+    chat: ChatOverlay,
     window: Window,
     display: Display<WindowSurface>,
 
@@ -222,9 +226,11 @@ impl RenderOptions {
     }
 }
 impl GameRanderer {
-    pub fn new(map_channel: Receiver<Map>, input_channel: Sender<WindowEvent>, window: Window, mut display: Display<WindowSurface>, options: &RenderOptions) -> GameRanderer {
+    pub fn new(map_channel: Receiver<Map>, chat_channel: Receiver<ChatOverlay>, input_channel: Sender<WindowEvent>, window: Window, mut display: Display<WindowSurface>, options: &RenderOptions) -> GameRanderer {
         GameRanderer {
             map_channel,
+            chat_channel,
+            chat: ChatOverlay::default(),
             input_channel,
             window,
             character_sheet: Character::load_all(Some(&mut display), &options.assets),
@@ -274,6 +280,19 @@ impl GameRanderer {
         frame.draw(&vertex_buffer, &indices, &program_err.unwrap(), &uniforms, &Default::default()).unwrap();
     }
 }
+impl GameRanderer {
+    /// Loads characters and maps downloaded after the window was opened.
+    fn load_missing(&mut self, map: &Map) {
+        if !self.map_pool.contains_key(&map.map_id) && let Some(info) = MapInformation::load(map.map_id,&mut Some(&mut self.display),&self.options.assets) {
+            self.map_pool.insert(map.map_id,info);
+        }
+        for character in map.characters.values() {
+            if !self.character_sheet.contains_key(&character.character) && let Some(sheet) = Character::load(character.character,&mut Some(&mut self.display),&self.options.assets) {
+                self.character_sheet.insert(character.character,sheet);
+            }
+        }
+    }
+}
 impl ApplicationHandler for GameRanderer {
     /// Event that happens of re-entering the window after leaving it.
     fn resumed(&mut self, _: &ActiveEventLoop) {
@@ -283,12 +302,34 @@ impl ApplicationHandler for GameRanderer {
     }
     /// Happens every frame of window rendering.
     fn window_event(&mut self, window: &ActiveEventLoop, _: WindowId, event: WindowEvent) {
-        self.input_channel.send(event.clone()).expect("Sending inputs to network core failed");
+        // Network core stoped, nothing to show anymore
+        if self.input_channel.send(event.clone()).is_err() {
+            window.exit();
+            return;
+        }
         match event {
             WindowEvent::CloseRequested => window.exit(),
             WindowEvent::RedrawRequested => {
-                let mut map_it = self.map_channel.try_iter().peekable();
-                while let Some(map) = map_it.next() && map_it.peek().is_none() {
+                let mut newest = Option::None;
+                loop {
+                    match self.map_channel.try_recv() {
+                        Ok(map) => newest = Some(map),
+                        Err(TryRecvError::Empty) => break,
+                        Err(TryRecvError::Disconnected) => {
+                            window.exit();
+                            return;
+                        },
+                    }
+                }
+                if let Some(chat) = self.chat_channel.try_iter().last() {
+                    self.chat = chat;
+                }
+                if let Some(map) = newest {
+                    self.load_missing(&map);
+                    // Characters that still arent loaded are not drawn
+                    let characters: Vec<&CharacterInstance> = map.characters.values()
+                        .filter(|character|self.character_sheet.contains_key(&character.character))
+                        .collect();
                     let mut target = self.display.draw();
                     let map_info = self.map_pool.get(&map.map_id);
                     if let Some(map) = map_info {
@@ -317,24 +358,25 @@ impl ApplicationHandler for GameRanderer {
                             }
                         }
                     }
-                    for (_,character) in &map.characters {
+                    for character in &characters {
                         character.draw(&mut self.display,&mut target,&self.character_sheet);
                     }
                     if self.options.coliders {
-                        for (_,character) in &map.characters {
+                        for character in &characters {
                             character.draw_colision_box(&mut self.display,&mut target,&self.character_sheet);
                         }
                     }
                     if self.options.hitboxes {
-                        for (_,character) in &map.characters {
+                        for character in &characters {
                             character.draw_hitbox(&mut self.display,&mut target,&self.character_sheet);
                         }
                     }
                     if self.options.hurtboxes {
-                        for (_,character) in &map.characters {
+                        for character in &characters {
                             character.draw_hurtbox(&mut self.display,&mut target,&self.character_sheet);
                         }
                     }
+                    self.chat.draw(&mut self.display,&mut target);
                     target.finish().unwrap();
                 }
             },
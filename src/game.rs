@@ -2,42 +2,109 @@ pub mod character;
 pub mod map;
 pub mod networking;
 pub mod physic;
+pub mod transport;
+pub mod rollback;
+pub mod replay;
+pub mod session;
+pub mod room;
+pub mod metrics;
+pub mod discovery;
+pub mod manifest;
+pub mod assets;
+pub mod clock;
+pub mod simulator;
+pub mod command;
 
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
 
-use std::collections::HashMap;
+use std::collections::{
+    BTreeMap,
+    HashMap,
+};
 use std::thread;
 use crate::game::networking::*;
+use crate::game::transport::*;
+use crate::game::rollback::*;
+use crate::game::replay::Recorder;
+use crate::game::session::Session;
+use crate::game::room::*;
+use crate::game::metrics::Metrics;
+use crate::game::discovery::ServerInfo;
+use crate::game::manifest::*;
+use crate::game::assets::*;
+use crate::game::clock::*;
+use crate::game::simulator::*;
+use crate::game::command::*;
+use crate::base::published::Published;
+use crate::base::bytes::ByteReader;
 use crate::game::{
     character::Character,
     map::*,
 
 };
 use std::{
-    io::prelude::*,
-    net::{TcpListener, TcpStream},
+    io::{prelude::*, BufReader},
+    net::{TcpListener, TcpStream, UdpSocket, SocketAddr, IpAddr},
 };
 
 /// Object that holds information about the player to be able play or be automaticly kicked out of
 /// the game after inactivity.
 #[derive(Clone)]
 pub struct Player {
-    #[allow(unused)]
-    id: String,
+    session: Session,
+    /// Ticks since the last packet from this player.
     last_ping: usize,
-    #[allow(unused)]
     name: String,
     instance: Option<u32>,   
     pub input: CharacterInput,
+    /// Where are snapshots send, known after first input packet.
+    addres: Option<SocketAddr>,
+    /// Newest input packet received from this player.
+    sequence: u32,
+    /// Inputs that werent applied yet by the client tick they are for, one is applied every tick.
+    inputs: BTreeMap<u32,CharacterInput>,
+    /// Client tick of the last applied input, snapshots acknowledge it.
+    applied: u32,
+    /// When were his last chat messages send, used for rate limiting.
+    chat_sent: std::collections::VecDeque<std::time::Instant>,
 }
 impl Player {
-    pub fn new(name: String) -> Player {
+    pub fn new(name: String, session_timeout: std::time::Duration) -> Player {
         Player {
-            id: uuid::Uuid::new_v4().to_string(),
+            session: Session::new(session_timeout),
             last_ping: 0,
             name,
             instance: None,
             input: CharacterInput::new(),
+            addres: None,
+            sequence: 0,
+            inputs: BTreeMap::new(),
+            applied: 0,
+            chat_sent: std::collections::VecDeque::new(),
+        }
+    }
+    /// Most inputs waiting to be applied, a client running ahead of the server would only add
+    /// latency.
+    const MAX_BUFFERED_INPUTS: usize = 16;
+    /// Stores inputs the player didnt send before, inputs for ticks that were already applied
+    /// are ignored.
+    pub fn buffer_inputs(&mut self, packet: &GameControlPacket, sequence: u32) {
+        for (tick,input) in packet.ticks(sequence) {
+            if tick > self.applied {
+                self.inputs.entry(tick).or_insert_with(||input.clone());
+            }
+        }
+        while self.inputs.len() > Self::MAX_BUFFERED_INPUTS && let Some((tick,_)) = self.inputs.pop_first() {
+            self.applied = tick;
+        }
+    }
+    /// Applies the oldest buffered input, if nothing new came the last one is kept.
+    pub fn next_input(&mut self) {
+        if let Some((tick,input)) = self.inputs.pop_first() {
+            self.input = input;
+            self.applied = tick;
         }
     }
 }
@@ -45,226 +112,664 @@ impl Player {
 pub struct Game {
     password: String,
     addres: String,
+    /// Name shown to clients looking for servers on the local network.
+    name: String,
 
-    players: Arc<Mutex<HashMap<String,Player>>>,
+    /// Every match running on this server by theyr id, room 0 is created on start and never
+    /// removed. Only the simulation thread touches them, others send commands.
+    rooms: BTreeMap<u32,Room>,
     characters: HashMap<u32,Character>,
+    /// Checksums of loaded characters and maps, clients have to have the same.
+    manifest: AssetManifest,
+    /// Root of the character, map and texture files, clients can download them from it.
+    assets: String,
 
-    map: Arc<Mutex<Option<Map>>>,
+    /// Map of the first room.
+    map_id: usize,
     map_pool: HashMap<usize,MapInformation>,
+    /// Ticks per second, can be changed by an admin while running.
     refresh_rate: f32,
+    /// Password for the admin commands, admin commands are disabled if its empty.
+    admin_password: String,
+    metrics: Metrics,
+    /// If set, the map is simulated in rollback mode and inputs are relayed to other players.
+    rollback: Option<RollbackSettings>,
+    /// File the first room is recorded in to.
+    recording: Option<String>,
+    /// How long can a session token be unused before it expires.
+    session_timeout: std::time::Duration,
+    /// How long can a player not send anything before he is removed.
+    idle_timeout: std::time::Duration,
+    /// Simulated bad network for everything send over UDP.
+    network: NetworkConditions,
 }
-impl Game {
+/// Everything the http threads need to know about the server.
+#[derive(Clone)]
+struct ConnectionSettings {
+    password: String,
+    /// Maps rooms can be created with.
+    maps: Vec<usize>,
+    admin_password: String,
+    /// Tick rate cant be changed while recording, replays are played with the starting one.
+    recording: bool,
+    manifest: AssetManifest,
+    assets: String,
+    /// Everything that needs the rooms goes to the simulation thread.
+    commands: SyncSender<Command>,
+    state: Arc<Published<ServerState>>,
+}
+impl Default for Game {
     /// Loads deafult values for testing on a local server
-    pub fn default() -> Game {
+    fn default() -> Game {
+        let assets = String::from("./assets/");
+        let characters = Character::load_all(Option::None, &assets);
+        let map_pool = MapInformation::load_all(None, &assets);
         Game {
             password: String::from(""),
             addres: String::from("127.0.0.1:3621"),
-            characters: Character::load_all(Option::None, &String::from("./assets/")),
-            players: Arc::new(HashMap::new().into()),
-            map: Arc::new(Some(Map::test()).into()),
-            map_pool: MapInformation::load_all(None, &String::from("./assets/")),
+            name: String::from("Nebula"),
+            manifest: AssetManifest::new(&characters,&map_pool,&assets),
+            assets,
+            characters,
+            rooms: BTreeMap::new(),
+            map_id: 0,
+            map_pool,
             refresh_rate: 60.0,
+            admin_password: String::new(),
+            metrics: Metrics::new(),
+            rollback: None,
+            recording: None,
+            session_timeout: Self::DEFAULT_SESSION_TIMEOUT,
+            idle_timeout: Self::DEFAULT_IDLE_TIMEOUT,
+            network: NetworkConditions::default(),
         }
     }
+}
+impl Game {
+    const DEFAULT_SESSION_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+    const DEFAULT_IDLE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+    /// Http connection that doesnt send a whole request or take a write in this time is closed.
+    const HTTP_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+    /// Most http connections handled at once, others get 503.
+    const MAX_CONNECTIONS: usize = 256;
+    /// Most rooms that can exist at once.
+    const MAX_ROOMS: usize = 64;
+    /// Most commands applied between two ticks, the rest waits for the next one so a flood of
+    /// requests cant stop the simulation.
+    const MAX_TICK_COMMANDS: usize = 1024;
+    /// Most commands waiting for the simulation thread, packets that come over it are dropped
+    /// and requests get 503.
+    const MAX_QUEUED_COMMANDS: usize = 4096;
     /// Prepears Game object for start and loades all maps and characters in to memory
     pub fn new(password: String,addres: String,refresh_rate: f32,map_id: usize,assets: String) -> Game {
+        let characters = Character::load_all(Option::None,&assets);
+        let map_pool = MapInformation::load_all(None,&assets);
         Game {
             password,
             addres,
-            characters: Character::load_all(Option::None,&assets),
-            players: Arc::new(HashMap::new().into()),
-            map: Arc::new(Some(Map::new(map_id)).into()),
-            map_pool: MapInformation::load_all(None,&assets),
+            name: String::from("Nebula"),
+            manifest: AssetManifest::new(&characters,&map_pool,&assets),
+            assets,
+            characters,
+            rooms: BTreeMap::new(),
+            map_id,
+            map_pool,
             refresh_rate,
+            admin_password: String::new(),
+            metrics: Metrics::new(),
+            rollback: None,
+            recording: None,
+            session_timeout: Self::DEFAULT_SESSION_TIMEOUT,
+            idle_timeout: Self::DEFAULT_IDLE_TIMEOUT,
+            network: NetworkConditions::default(),
         }
     }
-    /// Enables networking and starts the physic simulation in a nother thread
+    /// Sets how long can a player be silent before his session token expires.
+    pub fn session_timeout(&mut self, timeout: std::time::Duration) {
+        self.session_timeout = timeout;
+    }
+    /// Sets how long can a player be inactive before he is kicked out of the game.
+    /// Empty rooms are removed after the same time.
+    pub fn idle_timeout(&mut self, timeout: std::time::Duration) {
+        self.idle_timeout = timeout;
+    }
+    /// Name the server introduces itself with on the local network.
+    pub fn name(&mut self, name: String) {
+        self.name = name;
+    }
+    /// Enables admin commands that can be send with this password.
+    pub fn admin_password(&mut self, password: String) {
+        self.admin_password = password;
+    }
+    /// Records every tick of the first room in to a file that can be played with a client.
+    pub fn record(&mut self, path: String) {
+        self.recording = Some(path);
+    }
+    /// Switches to rollback netcode, clients have to use the same settings.
+    pub fn enable_rollback(&mut self, settings: RollbackSettings) {
+        self.rollback = Some(settings);
+    }
+    /// Delays, drops, duplicates and reorders snapshots and other packets send to clients.
+    pub fn simulate_network(&mut self, conditions: NetworkConditions) {
+        self.network = conditions;
+    }
+    /// Enables networking and runs the physic simulation in this thread. Http connections and UDP
+    /// packets are handled in other threads that send commands here, they are applied between
+    /// ticks.
     pub fn start(&mut self) {
         let listener = TcpListener::bind(self.addres.clone())
             .expect("Binding addres was unsucesfull");
+        let socket = UdpSocket::bind(self.addres.clone())
+            .expect("Binding UDP addres was unsucesfull");
+        let receiver = socket.try_clone().expect("Cloning UDP socket failed");
+        let simulator = NetworkSimulator::new(self.network,&socket);
 
-        let (map_pointer,player_pointer) = (Arc::clone(&self.map),Arc::clone(&self.players));
-        let password = self.password.clone();
+        let delta = 1.0/self.refresh_rate;
+        let mut main_room = Room::new(String::from("Main"),self.password.clone(),self.map_id,self.rollback);
+        if let Some(recorder) = self.recording.as_ref().and_then(|path|Recorder::new(path,delta)) {
+            main_room.record(recorder);
+        }
+        self.rooms.insert(0,main_room);
 
+        let (commands,inbox) = sync_channel(Self::MAX_QUEUED_COMMANDS);
+        let state = Arc::new(Published::new(ServerState::default()));
+        let settings = ConnectionSettings {
+            password: self.password.clone(),
+            maps: self.map_pool.keys().copied().collect(),
+            admin_password: self.admin_password.clone(),
+            recording: self.recording.is_some(),
+            manifest: self.manifest.clone(),
+            assets: self.assets.clone(),
+            commands: commands.clone(),
+            state: Arc::clone(&state),
+        };
+        let settings = Arc::new(settings);
+        let connections = Arc::new(AtomicUsize::new(0));
         let _ = thread::spawn(move ||
-            for stream_er in listener.incoming() {
-                let stream = stream_er.unwrap();
-                Self::handle_connection(stream,&map_pointer,&player_pointer,&password);
+            for stream in listener.incoming().flatten() {
+                if connections.fetch_add(1,Ordering::SeqCst) >= Self::MAX_CONNECTIONS {
+                    connections.fetch_sub(1,Ordering::SeqCst);
+                    let mut response = Response::error(ErrorCode::Busy);
+                    response.close();
+                    let _ = stream.set_write_timeout(Some(Self::HTTP_TIMEOUT));
+                    let _ = (&stream).write_all(&response.to_bytes());
+                    continue;
+                }
+                let (settings,connections) = (Arc::clone(&settings),Arc::clone(&connections));
+                let _ = thread::spawn(move || {
+                    Self::handle_connection(stream,&settings);
+                    connections.fetch_sub(1,Ordering::SeqCst);
+                });
             }
         );
-        let delta = 1.0/self.refresh_rate;
-        let frame_time = std::time::Duration::from_secs_f32(delta);
+        let _ = thread::spawn(move || {
+            // Receive only ends when the socket itself failed
+            while let Some((packet,addres)) = Packet::receive(&receiver) {
+                // Lost packets are handled by clients anyway, waiting would only make them late
+                if let Err(TrySendError::Disconnected(_)) = commands.try_send(Command::Packet(packet,addres)) {
+                    return;
+                }
+            }
+            println!("Receiving UDP packets failed, inputs and pings are not handled anymore");
+        });
         loop {
             let next_frame = std::time::Instant::now();
+            let delta = 1.0/self.refresh_rate;
+            let frame_time = std::time::Duration::from_secs_f32(delta);
+            let idle_ticks = (self.idle_timeout.as_secs_f32()*self.refresh_rate) as usize;
 
-            let players_input = Self::players_clone(&self.players);
-            loop {
-                if let Ok(ref mut map_opt) = self.map.try_lock() &&
-                    let Some(map) = &mut **map_opt {
-                        map.counter += 1;
-                        map.set_inputs(players_input);
-                        map.update(&self.characters,&self.map_pool,&delta);
-                        break;
-
+            self.rooms.retain(|id,room|*id == 0 || room.empty_ticks <= idle_ticks);
+            let mut snapshots = Vec::new();
+            let mut watched = Vec::new();
+            let (mut players,mut spectators,mut instances) = (0,0,0);
+            for room in self.rooms.values_mut() {
+                if let Some(snapshot) = room.tick(&self.characters,&self.map_pool,&delta,idle_ticks) {
+                    snapshots.push((room.targets(),snapshot));
                 }
+                watched.extend(room.spectator_targets());
+                players += room.players.len();
+                spectators += room.spectators.len();
+                instances += room.map.characters.len();
             }
+            self.metrics.population(self.rooms.len(),players,spectators,instances);
+            self.publish(&state);
 
-            thread::sleep(frame_time-(std::time::Instant::now() - next_frame));
-        }
-    }
-    /// Clones the list of all players and returnes it.
-    fn players_clone(players_ref: &Arc<Mutex<HashMap<String,Player>>>) -> HashMap<String,Player> {
-        loop { if let Ok(ref mut players) = players_ref.try_lock(){
-            let out = players.clone();
-            let _ = players.iter_mut().map(|(_,p)|{p.last_ping+=1;p.input.reset();});
-            return out;
-        }};
-    }
-    /// If no player of that name exists, create that player.
-    fn new_player(input: JoinRequest,players_ref: &Arc<Mutex<HashMap<String,Player>>>) -> Result<(),Response> {
-        loop { if let Ok(ref mut players) = players_ref.try_lock(){
-            if let Option::None = players.get(&input.player_name) {
-                players.insert(input.player_name.clone(),Player::new(input.player_name));
-                return Ok(());
-            } else {
-                return Err(Response::status(ResponseStatus::Forbiden));
-            }
-        }};
-    }
-    /// Updates player according to information in GameControlPacked sended by that player.
-    fn update_player(input: GameControlPacket,players_ref: &Arc<Mutex<HashMap<String,Player>>>) -> Result<(),Response> {
-        loop { if let Ok(ref mut players) = players_ref.try_lock(){
-            if let Some(player) = players.get_mut(&input.player) {
-                player.input = input.input;
-                player.last_ping = 0;
-                return Ok(());
-            } else {
-                return Err(Response::status(ResponseStatus::None));
+            let mut sent = 0;
+            for (targets,(sequence,payload)) in snapshots {
+                for (addres,ack,instance) in targets {
+                    sent += Packet::new(PacketKind::Snapshot,sequence,ack,snapshot_payload(instance,&payload)).send_through(&socket,simulator.as_ref(),&addres);
+                }
             }
-        }};
-    }
-    /// Logic for switching characters between states.
-    fn player_switch_char(character: Option<u32>, player_name: String ,map_ref: &Arc<Mutex<Option<Map>>>, players_ref: &Arc<Mutex<HashMap<String,Player>>>) -> Result<(),Response> {
-        let instance_id_op = loop { if let Ok(ref mut players) = players_ref.try_lock(){
-            if let Some(player) = players.get_mut(&player_name) {
-                player.last_ping = 0;
-                break player.instance;
-            } else {
-                return Err(Response::status(ResponseStatus::Unauthorized));
+            for (addres,sequence,payload) in watched {
+                sent += Packet::new(PacketKind::Snapshot,sequence,0,snapshot_payload(None,&payload)).send_through(&socket,simulator.as_ref(),&addres);
             }
-        }};
-        let current_character_id = loop { if let Ok(ref mut map_op) = map_ref.try_lock() {
-            if let Some(map) = &**map_op {
-                if let Some(id) = instance_id_op && let Some(instance) = map.characters.get(&id) {
-                    break Some(instance.character);
-                } else {
-                    break Option::None;
-                }
-            }else{return Err(Response::status(ResponseStatus::NotImplemented));}
-        }};
 
-        match (current_character_id,character) {
-            (Option::None,Option::Some(new_id)) => {
-                let new_object_id = loop { if let Ok(ref mut map_op) = map_ref.try_lock() {
-                    if let Some(map) = &mut **map_op {
-                        break map.new_istance(new_id);
-                    }else{return Err(Response::status(ResponseStatus::Error));}
-                }};
-                loop { if let Ok(ref mut players) = players_ref.try_lock(){
-                    if let Some(player) = players.get_mut(&player_name) {
-                        player.instance = Some(new_object_id);
-                        break;
-                    } else {
-                        return Err(Response::status(ResponseStatus::Error));
-                    }
-                }}
+            let elapsed = next_frame.elapsed();
+            self.metrics.udp_sent(sent);
+            self.metrics.tick(elapsed,elapsed > frame_time);
+            // Tick that took too long is followed right away by the next one
+            self.apply_commands(&inbox,next_frame + frame_time,&socket,simulator.as_ref());
+        }
+    }
+    /// Applies commands as they come until the next tick should start.
+    fn apply_commands(&mut self, inbox: &Receiver<Command>, next_tick: std::time::Instant, socket: &UdpSocket, simulator: Option<&NetworkSimulator>) {
+        let mut applied = 0;
+        while applied < Self::MAX_TICK_COMMANDS &&
+            let Ok(command) = inbox.recv_timeout(next_tick.saturating_duration_since(std::time::Instant::now())) {
+                self.apply(command,socket,simulator);
+                applied += 1;
+        }
+        // Too many commands came or every sender is gone
+        thread::sleep(next_tick.saturating_duration_since(std::time::Instant::now()));
+    }
+    fn apply(&mut self, command: Command, socket: &UdpSocket, simulator: Option<&NetworkSimulator>) {
+        // Reply fails only when the connection thread is gone, nobody is left to answer
+        let _ = match command {
+            Command::Join(input,ip,reply) => reply.send(self.player_join(input,ip)),
+            Command::Spectate(input,ip,reply) => reply.send(self.spectator_join(input,ip)),
+            Command::Leave(token,reply) => reply.send(self.player_leave(token)),
+            Command::Resume(token,reply) => reply.send(self.player_resume(token)),
+            Command::Chat(input,reply) => reply.send(self.player_chat(input)),
+            Command::SwitchCharacter(character,token,reply) => reply.send(self.player_switch_char(character,token)),
+            Command::CreateRoom(input,reply) => reply.send(self.create_room(input)),
+            Command::Admin(input,reply) => reply.send(self.admin_command(input)),
+            Command::Metrics(reply) => reply.send(Response::new(ResponseStatus::Ok,BodyType::Text,&self.metrics.render())),
+            Command::Served {method,path,status,bytes} => {
+                self.metrics.request(&method,&path,status,bytes);
+                Ok(())
             },
-            (Option::Some(_),Option::None) => {
-                loop { if let Ok(ref mut map_op) = map_ref.try_lock() {
-                    if let Some(map) = &mut **map_op && let Some(id) = instance_id_op {
-                        map.characters.remove(&id);
-                        break;
-                    }else{return Err(Response::status(ResponseStatus::Error));}
-                }}
-                loop { if let Ok(ref mut players) = players_ref.try_lock(){
-                    if let Some(player) = players.get_mut(&player_name) {
-                        player.instance = Option::None;
-                        break;
-                    } else {
-                        return Err(Response::status(ResponseStatus::Error));
+            Command::Packet(packet,addres) => {
+                self.handle_packet(packet,addres,socket,simulator);
+                Ok(())
+            },
+        };
+    }
+    /// Publishes room list and the map of the first room for connection threads.
+    fn publish(&self, state: &Published<ServerState>) {
+        state.publish(ServerState {
+            rooms: self.rooms.iter().map(|(id,room)|room.info(*id)).collect(),
+            map: self.rooms.get(&0).map_or(Vec::new(),|room|room.map.as_bytes()),
+        });
+    }
+    /// Room the session token of a player or spectator belongs to.
+    fn room_of<'a>(rooms: &'a mut BTreeMap<u32,Room>, token: &String) -> Option<&'a mut Room> {
+        rooms.values_mut().find(|room|room.has_token(token))
+    }
+    /// Handles every packet that came over UDP.
+    /// Input packet carries inputs of the last few client ticks, sequence is the tick of the
+    /// newest one. In rollback mode the client tick is the tick of the simulation, the inputs are
+    /// passed to simulation and the newest is relayed to all other players of the room.
+    /// Probes from clients looking for servers on the local network are answered with ServerInfo.
+    /// Pings are answered with the current tick of the room, clients use it to keep up with the
+    /// server.
+    fn handle_packet(&mut self, packet: Packet, addres: SocketAddr, socket: &UdpSocket, simulator: Option<&NetworkSimulator>) {
+        match packet.kind {
+            PacketKind::Input =>
+                if let Some(input) = GameControlPacket::from_bytes(&packet.payload) {
+                    let mut relay_to = None;
+                    if let Some(room) = Self::room_of(&mut self.rooms,&input.token) &&
+                        let Some(Some(instance)) = room.update_player(&input,packet.sequence,addres) &&
+                        room.is_rollback() {
+                            // Only the newest input is relayed, others got it with earlier packets
+                            let mut newest = None;
+                            for (tick,input) in input.ticks(packet.sequence) {
+                                let tick_input = TickInput {instance, tick: tick as usize, input: input.clone()};
+                                room.add_input(tick_input.clone());
+                                newest = Some(tick_input);
+                            }
+                            relay_to = newest.map(|tick_input|(tick_input,room.addreses()));
                     }
-                }}
+                    if let Some((tick_input,addreses)) = relay_to {
+                        let relayed = Packet::new(PacketKind::Inputs,packet.sequence,0,tick_input.as_bytes());
+                        let mut sent = 0;
+                        for other in addreses {
+                            if other != addres {
+                                sent += relayed.send_through(socket,simulator,&other);
+                            }
+                        }
+                        self.metrics.udp_sent(sent);
+                    }
+                },
+            PacketKind::Watch =>
+                if let Some(token) = ByteReader::new(&packet.payload).string() &&
+                    let Some(room) = Self::room_of(&mut self.rooms,&token) {
+                        room.watch(&token,addres);
+                },
+            PacketKind::Probe => {
+                let info = ServerInfo {
+                    name: self.name.clone(),
+                    map_id: self.rooms.get(&0).map_or(0,|room|room.map.map_id),
+                    players: self.rooms.values().map(|room|room.players.len() as u32).sum(),
+                    rooms: self.rooms.len() as u32,
+                    password: self.rooms.get(&0).is_some_and(|room|!room.check_password("")),
+                };
+                Packet::new(PacketKind::Info,0,0,info.as_bytes()).send_to(socket,&addres);
             },
-            (Option::Some(_),Option::Some(new_id)) => 
-                loop { if let Ok(ref mut map_op) = map_ref.try_lock() {
-                    if let Some(map) = &mut **map_op && let Some(instance_id) = instance_id_op && let Some(char_instance) = map.characters.get_mut(&instance_id) {
-                        char_instance.character = new_id;
-                        char_instance.reset();
-                        break;
-                    }else{return Err(Response::status(ResponseStatus::Error));}
-                }},
-            (Option::None,Option::None) => {},
+            PacketKind::Ping =>
+                if let Some(request) = TimeRequest::from_bytes(&packet.payload) &&
+                    let Some((tick,progress,rate)) = Self::room_of(&mut self.rooms,&request.token).map(|room|room.time()) {
+                        let response = TimeResponse {client_time: request.client_time, tick, progress, rate};
+                        Packet::new(PacketKind::Pong,0,packet.sequence,response.as_bytes()).send_through(socket,simulator,&addres);
+                },
+            PacketKind::Snapshot | PacketKind::Inputs | PacketKind::Info | PacketKind::Pong => {},
         }
-
-        Ok(())
-    }
-    /// Returns current map state in a response object that is ready to be send.
-    fn get_map_res(map_ref: &Arc<Mutex<Option<Map>>>) -> Response {
-        loop { if let Ok(map) = map_ref.try_lock() {
-            if let Some(out) = (*map).clone() {
-                break Response::new(ResponseStatus::Ok,BodyType::Binary, &out.to_string());
-            } else {
-                break Response::status(ResponseStatus::Ok);
-            }
-        }}
+    }
+    /// Checks if the client speaks the same protocol and has the same assets, otherwise returns
+    /// response listing the differences.
+    fn check_client(protocol: u32, assets: &AssetManifest, settings: &ConnectionSettings) -> Option<Response> {
+        let (characters,maps) = settings.manifest.compare(assets);
+        if protocol == PROTOCOL_VERSION && characters.is_empty() && maps.is_empty() {
+            return None;
+        }
+        let mismatch = AssetMismatch {protocol: PROTOCOL_VERSION, characters, maps};
+        Some(Response::new(ResponseStatus::Conflict,BodyType::JSON,&mismatch.to_string()))
+    }
+    /// Adds player to a room if the password is right and the name isnt taken, returns his token.
+    fn player_join(&mut self, input: JoinRequest, ip: Option<IpAddr>) -> Response {
+        let Some(room) = self.rooms.get_mut(&input.room) else {
+            return Response::error(ErrorCode::UnknownRoom);
+        };
+        if !room.check_password(&input.server_password) {
+            return Response::error(ErrorCode::WrongPassword);
+        }
+        if room.is_banned(&input.player_name,ip) {
+            return Response::error(ErrorCode::Banned);
+        }
+        match room.join(input.player_name,self.session_timeout) {
+            Ok(token) => Response::new(ResponseStatus::Ok,BodyType::JSON,&JoinResponse {token,chat: room.chat_history()}.to_string()),
+            Err(code) => Response::error(code),
+        }
+    }
+    /// Adds spectator to a room if the password is right and he isnt banned, returns his token.
+    fn spectator_join(&mut self, input: SpectateRequest, ip: Option<IpAddr>) -> Response {
+        let Some(room) = self.rooms.get_mut(&input.room) else {
+            return Response::error(ErrorCode::UnknownRoom);
+        };
+        if !room.check_password(&input.server_password) {
+            return Response::error(ErrorCode::WrongPassword);
+        }
+        if room.is_banned(&input.name,ip) {
+            return Response::error(ErrorCode::Banned);
+        }
+        match room.spectate(input.name,input.delay,self.session_timeout) {
+            Ok(token) => Response::new(ResponseStatus::Ok,BodyType::JSON,&JoinResponse {token,chat: room.chat_history()}.to_string()),
+            Err(code) => Response::error(code),
+        }
+    }
+    /// Removes the player and his character or the spectator, the session token stops working.
+    fn player_leave(&mut self, token: String) -> Response {
+        if let Some(room) = Self::room_of(&mut self.rooms,&token) &&
+            (room.spectators.contains_key(&token) || room.authenticate(&token).is_some() || room.disconnected.contains_key(&token)) {
+            room.leave(&token);
+            return Response::status(ResponseStatus::Ok);
+        }
+        Response::error(ErrorCode::InvalidSession)
+    }
+    /// Continues session of a player or spectator that lost connection.
+    fn player_resume(&mut self, token: String) -> Response {
+        if let Some(room) = Self::room_of(&mut self.rooms,&token) && room.resume(&token) {
+            return Response::status(ResponseStatus::Ok);
+        }
+        Response::error(ErrorCode::InvalidSession)
+    }
+    /// Sends chat message of the player to his room.
+    fn player_chat(&mut self, input: ChatRequest) -> Response {
+        let Some(room) = Self::room_of(&mut self.rooms,&input.token) else {
+            return Response::error(ErrorCode::InvalidSession);
+        };
+        match room.chat(&input.token,&input.text) {
+            Ok(()) => Response::status(ResponseStatus::Ok),
+            Err(code) => Response::error(code),
+        }
+    }
+    /// Switches character of the player and returns the map of his room.
+    fn player_switch_char(&mut self, character: Option<u32>, token: String) -> Response {
+        let Some(room) = Self::room_of(&mut self.rooms,&token) else {
+            return Response::error(ErrorCode::InvalidSession);
+        };
+        match room.switch_character(&token,character) {
+            Ok(()) => Response::binary(ResponseStatus::Ok,room.map.as_bytes()),
+            Err(code) => Response::error(code),
+        }
+    }
+    /// Creates a new room and returns its information.
+    fn create_room(&mut self, input: CreateRoomRequest) -> Response {
+        if self.rooms.len() >= Self::MAX_ROOMS {
+            return Response::error(ErrorCode::TooManyRooms);
+        }
+        let id = self.rooms.keys().next_back().map_or(0,|id|id + 1);
+        let room = Room::new(input.name,input.password,input.map_id,self.rollback);
+        let info = room.info(id);
+        self.rooms.insert(id,room);
+        Response::new(ResponseStatus::Ok,BodyType::JSON,&serde_json::to_string(&info).unwrap())
+    }
+    /// Runs admin command on a room or the whole server, passwords and arguments are checked by
+    /// the connection thread.
+    fn admin_command(&mut self, input: AdminRequest) -> Response {
+        if let AdminCommand::SetRefreshRate(rate) = input.command {
+            self.refresh_rate = rate;
+            return Response::status(ResponseStatus::Ok);
+        }
+        let Some(room) = self.rooms.get_mut(&input.room) else {
+            return Response::error(ErrorCode::UnknownRoom);
+        };
+        match input.command {
+            AdminCommand::ChangeMap(map_id) => room.map.change_map(map_id),
+            AdminCommand::Kick(name) => if !room.kick(&name,false) {
+                return Response::error(ErrorCode::UnknownPlayer);
+            },
+            AdminCommand::Ban(name) => {room.kick(&name,true);},
+            AdminCommand::Pause => room.set_paused(true),
+            AdminCommand::Resume => room.set_paused(false),
+            AdminCommand::SetPassword(password) => room.set_password(password),
+            AdminCommand::Mute(name) => room.mute(&name,true),
+            AdminCommand::Unmute(name) => room.mute(&name,false),
+            AdminCommand::SetRefreshRate(_) => {},
+        }
+        Response::status(ResponseStatus::Ok)
+    }
+    /// Checks everything about a room creation that doesnt need the rooms.
+    fn check_create_room(input: &CreateRoomRequest, settings: &ConnectionSettings) -> Option<Response> {
+        if input.server_password != settings.password {
+            return Some(Response::error(ErrorCode::WrongPassword));
+        }
+        if !settings.maps.contains(&input.map_id) {
+            return Some(Response::error(ErrorCode::UnknownMap));
+        }
+        None
+    }
+    /// Checks admin password and arguments of the command.
+    fn check_admin(input: &AdminRequest, settings: &ConnectionSettings) -> Option<Response> {
+        if settings.admin_password.is_empty() {
+            return Some(Response::error(ErrorCode::AdminDisabled));
+        }
+        if input.admin_password != settings.admin_password {
+            return Some(Response::error(ErrorCode::WrongAdminPassword));
+        }
+        match input.command {
+            AdminCommand::SetRefreshRate(_) if settings.recording => Some(Response::error(ErrorCode::Recording)),
+            AdminCommand::SetRefreshRate(rate) if !(1.0..=1000.0).contains(&rate) => Some(Response::error(ErrorCode::InvalidTickRate)),
+            AdminCommand::ChangeMap(map_id) if !settings.maps.contains(&map_id) => Some(Response::error(ErrorCode::UnknownMap)),
+            _ => None,
+        }
+    }
+    /// List of all rooms.
+    fn list_rooms(settings: &ConnectionSettings) -> Response {
+        Response::new(ResponseStatus::Ok,BodyType::JSON,&RoomInfo::list_to_string(&settings.state.get().rooms))
+    }
+    /// Character or map definition (by id) or a texture (by path relative to the assets root).
+    /// Only loaded definitions and files inside of the assets root are served.
+    fn get_asset(path: &str, settings: &ConnectionSettings) -> Response {
+        let assets = &settings.assets;
+        let result = match path.split_once('/') {
+            Some(("characters",id)) => match id.parse::<u32>() {
+                Ok(id) if id != 0 && settings.manifest.characters.contains_key(&id) => serve_definition(Character::path(id,assets),assets),
+                _ => Err(ResponseStatus::None),
+            },
+            Some(("maps",id)) => match id.parse::<usize>() {
+                Ok(id) if id != 0 && settings.manifest.maps.contains_key(&id) => serve_definition(MapInformation::path(id,assets),assets),
+                _ => Err(ResponseStatus::None),
+            },
+            Some(("textures",texture)) => serve_texture(texture,assets),
+            _ => Err(ResponseStatus::None),
+        };
+        match result {
+            Ok(bytes) => Response::binary(ResponseStatus::Ok,bytes),
+            Err(status) => Response::status(status),
+        }
+    }
+    /// Returns map state of the first room after the last tick in a response object that is
+    /// ready to be send.
+    fn get_map_res(settings: &ConnectionSettings) -> Response {
+        Response::binary(ResponseStatus::Ok,settings.state.get().map.clone())
     }
     /// Main function for handeling all network and managing what will be done with any receaved packed.
+    /// Every connection has its own thread and can send more requests one after another (HTTP/1.1
+    /// keep-alive), it is closed after Connection: close, a timeout or a request that couldnt be read.
+    /// Bodies over MAX_BODY_LENGTH get 413, known paths with a wrong method 405.
     /// Basic browser requests are redirected to the project github page
-    /// All endpoints returns current state of the map if succesfull. 
     ///
     /// Get / -> redirects on "github.com/3ther-joyboy/Nebula"
     ///
-    /// Get /map/ -> Current state of the map.
+    /// Get /rooms/ -> List of rooms.
     ///
-    /// Post /map/ -> New player joining.
+    /// Post /rooms/ -> Creates a new room, returns its information.
     ///
-    /// Put /map/ -> Waiting for character inputs
+    /// Get /map/ -> Current state of the map of the first room.
     ///
-    /// Put /character/ -> Logic for chaning characters
-    fn handle_connection(mut stream: TcpStream,map_ref: &Arc<Mutex<Option<Map>>>,players_ref: &Arc<Mutex<HashMap<String,Player>>>, password: &String) {
-        let headers = Headers::new(&mut stream);
-
-        let matching = (headers.request_type.as_str(),headers.path.as_str());
-        let response = 
-            match matching {
-                ("GET","/") => Response::new(ResponseStatus::Ok,BodyType::HTML, "<head><meta http-equiv=\"refresh\" content=\"0; url=https://github.com/3ther-joyboy/Nebula\" />"),
-                ("PUT","/character/") => 
-                    if let Some(input) = get_responce::<CharacterSwitchRequest>(&mut stream,headers) {
-                        if *password == input.server_password {
-                            if let Err(out) = Self::player_switch_char(input.character,input.player_name,map_ref,players_ref) {
-                                out
-                            } else {Self::get_map_res(&map_ref)}
-                        }else{Response::status(ResponseStatus::Unauthorized)}
-                    }else{Response::status(ResponseStatus::ParseError)},
-                ("POST","/map/") => 
-                    if let Some(input) = get_responce::<JoinRequest>(&mut stream,headers) {
-                        if *password == input.server_password {
-                            if let Err(error_msg) = Self::new_player(input, &players_ref) {
-                                error_msg
-                            }else{Self::get_map_res(&map_ref)}
-                        }else{Response::status(ResponseStatus::Unauthorized)}
-                    }else{Response::status(ResponseStatus::ParseError)},
-                ("PUT","/map/") => {
-                    if let Some(input) = get_responce::<GameControlPacket>(&mut stream,headers){
-                        if *password == input.server_password {
-                            if let Err(error_msg) = Self::update_player(input, &players_ref) {
-                                error_msg
-                            }else{Self::get_map_res(&map_ref)}
-                        }else{Response::status(ResponseStatus::Unauthorized)}
-                    }else{Response::status(ResponseStatus::ParseError)}},
-                ("GET","/map/") => Self::get_map_res(&map_ref),
-                (_,_) => Response::status(ResponseStatus::None),
+    /// Post /map/ -> New player joining a room, returns session token used by all other requests.
+    ///
+    /// Delete /map/ -> Player or spectator leaving.
+    ///
+    /// Post /resume/ -> Player or spectator that lost connection continues with the same session,
+    /// 401 when the session expired and the client has to join again.
+    ///
+    /// Post /spectate/ -> Spectator joining a room, returns session token that has to be send in
+    /// watch packets.
+    ///
+    /// Character inputs and map snapshots are send over UDP, see handle_packet.
+    ///
+    /// Put /character/ -> Logic for chaning characters, returns the map of the players room.
+    ///
+    /// Post /admin/ -> Admin commands (map change, kick, ban, pause, password, tick rate, mute).
+    ///
+    /// Post /chat/ -> Chat message of a player, it is send to his room with the next snapshots.
+    ///
+    /// Get /metrics -> Server metrics in Prometheus text format.
+    ///
+    /// Get /assets/characters/{id}, /assets/maps/{id} -> Definition with texture paths relative
+    /// to the assets root.
+    ///
+    /// Get /assets/textures/{path} -> Texture file used by a definition.
+    fn handle_connection(mut stream: TcpStream,settings: &ConnectionSettings) {
+        let ip = stream.peer_addr().ok().map(|addres|addres.ip());
+        if stream.set_read_timeout(Some(Self::HTTP_TIMEOUT)).is_err() || stream.set_write_timeout(Some(Self::HTTP_TIMEOUT)).is_err() {
+            return;
+        }
+        let Ok(reader) = stream.try_clone() else {return};
+        let mut reader = BufReader::new(DeadlineStream::new(reader));
+        loop {
+            // Whole request has to come in time, not just each part of it
+            reader.get_mut().set_deadline(std::time::Instant::now() + Self::HTTP_TIMEOUT);
+            let (method,path,response) = match Request::read(&mut reader) {
+                Ok(request) => {
+                    let mut response = Self::route(&request,ip,settings);
+                    if !request.headers.keep_alive {
+                        response.close();
+                    }
+                    (request.headers.request_type,request.headers.path,response)
+                },
+                Err(RequestError::Closed) => return,
+                Err(error) => {
+                    // Rest of the request cant be skipped safely so the connection is closed
+                    let code = match error {
+                        RequestError::TooLarge => ErrorCode::TooLarge,
+                        RequestError::Unsupported => ErrorCode::NotImplemented,
+                        _ => ErrorCode::ParseError,
+                    };
+                    let mut response = Response::error(code);
+                    response.close();
+                    (String::new(),String::new(),response)
+                },
             };
-        stream.write_all(response.to_string().as_bytes()).unwrap();
+            let bytes = response.to_bytes();
+            // Metrics arent worth waiting for
+            let _ = settings.commands.try_send(Command::Served {method,path,status: response.status_code(),bytes: bytes.len()});
+            if stream.write_all(&bytes).is_err() || response.closes() {
+                return;
+            }
+        }
+    }
+    /// Methods a path takes, empty when the path doesnt exist.
+    fn allowed_methods(path: &str) -> &'static [&'static str] {
+        match path {
+            "/" | "/metrics" => &["GET"],
+            "/rooms/" => &["GET","POST"],
+            "/map/" => &["GET","POST","DELETE"],
+            "/character/" => &["PUT"],
+            "/admin/" | "/spectate/" | "/chat/" | "/resume/" => &["POST"],
+            path if path.starts_with("/assets/") => &["GET"],
+            _ => &[],
+        }
+    }
+    /// Response for a single request, see handle_connection.
+    fn route(request: &Request,ip: Option<IpAddr>,settings: &ConnectionSettings) -> Response {
+        let commands = &settings.commands;
+        let matching = (request.headers.request_type.as_str(),request.headers.path.as_str());
+        match matching {
+            ("GET","/") => Response::new(ResponseStatus::Ok,BodyType::HTML, "<head><meta http-equiv=\"refresh\" content=\"0; url=https://github.com/3ther-joyboy/Nebula\" />"),
+            ("GET","/rooms/") => Self::list_rooms(settings),
+            ("POST","/rooms/") => 
+                if let Some(input) = request.json::<CreateRoomRequest>() {
+                    Self::check_create_room(&input,settings)
+                        .unwrap_or_else(||Command::ask(commands,|reply|Command::CreateRoom(input,reply)))
+                }else{Response::error(ErrorCode::ParseError)},
+            ("PUT","/character/") => 
+                if let Some(input) = request.json::<CharacterSwitchRequest>() {
+                    if let Some(id) = input.character && !settings.manifest.characters.contains_key(&id) {
+                        Response::error(ErrorCode::UnknownCharacter)
+                    } else {
+                        Command::ask(commands,|reply|Command::SwitchCharacter(input.character,input.token,reply))
+                    }
+                }else{Response::error(ErrorCode::ParseError)},
+            ("POST","/map/") => 
+                if let Some(input) = request.json::<JoinRequest>() {
+                    Self::check_client(input.protocol,&input.assets,settings)
+                        .unwrap_or_else(||Command::ask(commands,|reply|Command::Join(input,ip,reply)))
+                }else{Response::error(ErrorCode::ParseError)},
+            ("GET","/map/") => Self::get_map_res(settings),
+            ("GET","/metrics") => Command::ask(commands,Command::Metrics),
+            ("POST","/admin/") => 
+                if let Some(input) = request.json::<AdminRequest>() {
+                    Self::check_admin(&input,settings)
+                        .unwrap_or_else(||Command::ask(commands,|reply|Command::Admin(input,reply)))
+                }else{Response::error(ErrorCode::ParseError)},
+            ("POST","/spectate/") => 
+                if let Some(input) = request.json::<SpectateRequest>() {
+                    Self::check_client(input.protocol,&input.assets,settings)
+                        .unwrap_or_else(||Command::ask(commands,|reply|Command::Spectate(input,ip,reply)))
+                }else{Response::error(ErrorCode::ParseError)},
+            ("POST","/chat/") => 
+                if let Some(input) = request.json::<ChatRequest>() {
+                    Command::ask(commands,|reply|Command::Chat(input,reply))
+                }else{Response::error(ErrorCode::ParseError)},
+            ("DELETE","/map/") => 
+                if let Some(input) = request.json::<LeaveRequest>() {
+                    Command::ask(commands,|reply|Command::Leave(input.token,reply))
+                }else{Response::error(ErrorCode::ParseError)},
+            ("POST","/resume/") => 
+                if let Some(input) = request.json::<ResumeRequest>() {
+                    Command::ask(commands,|reply|Command::Resume(input.token,reply))
+                }else{Response::error(ErrorCode::ParseError)},
+            ("GET",path) if path.starts_with("/assets/") => Self::get_asset(&path["/assets/".len()..],settings),
+            (_,path) => match Self::allowed_methods(path) {
+                [] => Response::status(ResponseStatus::None),
+                methods => {
+                    let mut response = Response::error(ErrorCode::MethodNotAllowed);
+                    response.header("Allow",&methods.join(", "));
+                    response
+                },
+            },
+        }
     }
 }
@@ -0,0 +1,157 @@
+use serde_json::Value;
+use std::{
+    fs,
+    path::{
+        Component,
+        Path,
+        PathBuf,
+    },
+};
+use crate::base::bytes::checksum;
+use crate::game::{
+    character::Character,
+    manifest::{
+        AssetManifest,
+        AssetMismatch,
+    },
+    map::MapInformation,
+    networking::ResponseStatus,
+};
+
+/// Calls change on path of every texture inside of a character or map definition.
+/// Texture is any object with "path" and "dimensions".
+fn texture_paths(value: &mut Value, change: &mut dyn FnMut(&mut String) -> bool) -> bool {
+    match value {
+        Value::Object(object) => {
+            if object.contains_key("dimensions") && let Some(Value::String(path)) = object.get_mut("path") && !change(path) {
+                return false;
+            }
+            object.values_mut().all(|value|texture_paths(value,change))
+        },
+        Value::Array(array) => array.iter_mut().all(|value|texture_paths(value,change)),
+        _ => true,
+    }
+}
+/// Checksum of a definition that doesnt depend on formating or on where its textures are.
+pub fn definition_checksum(bytes: &[u8]) -> u64 {
+    let Ok(mut definition) = serde_json::from_slice::<Value>(bytes) else {
+        return checksum(bytes);
+    };
+    texture_paths(&mut definition,&mut |path|{path.clear(); true});
+    checksum(&serde_json::to_vec(&definition).unwrap_or_default())
+}
+/// Path relative to the assets root that doesnt leave it, None if it would.
+fn relative_path(path: &str) -> Option<PathBuf> {
+    let path = Path::new(path);
+    if path.components().all(|component|matches!(component,Component::Normal(_))) {
+        Some(path.to_path_buf())
+    } else {
+        None
+    }
+}
+/// Finds file inside of the assets root, anything outside of it (even through links) is refused.
+fn sandboxed(assets: &String, path: &Path) -> Option<PathBuf> {
+    let root = fs::canonicalize(assets).ok()?;
+    let file = fs::canonicalize(path).ok()?;
+    if file.starts_with(&root) && file.is_file() {
+        Some(file)
+    } else {
+        None
+    }
+}
+/// Definition file ready to be send to a client, texture paths are changed to be relative to the
+/// assets root. Definitions with textures outside of the assets root are refused.
+pub fn serve_definition(path: String, assets: &String) -> Result<Vec<u8>,ResponseStatus> {
+    let file = sandboxed(assets,Path::new(&path)).ok_or(ResponseStatus::None)?;
+    let bytes = fs::read(file).map_err(|_|ResponseStatus::Error)?;
+    let mut definition: Value = serde_json::from_slice(&bytes).map_err(|_|ResponseStatus::Error)?;
+    let root = fs::canonicalize(assets).map_err(|_|ResponseStatus::Error)?;
+    let relative = texture_paths(&mut definition,&mut |path|{
+        let Some(texture) = sandboxed(assets,Path::new(path.as_str())) else {
+            return false;
+        };
+        let Ok(inside) = texture.strip_prefix(&root) else {
+            return false;
+        };
+        *path = inside.components()
+            .filter_map(|component|component.as_os_str().to_str())
+            .collect::<Vec<&str>>()
+            .join("/");
+        true
+    });
+    if !relative {
+        return Err(ResponseStatus::Forbiden);
+    }
+    serde_json::to_vec(&definition).map_err(|_|ResponseStatus::Error)
+}
+/// Texture file by its path relative to the assets root.
+pub fn serve_texture(path: &str, assets: &String) -> Result<Vec<u8>,ResponseStatus> {
+    let relative = relative_path(path).ok_or(ResponseStatus::Forbiden)?;
+    let file = sandboxed(assets,&Path::new(assets).join(relative)).ok_or(ResponseStatus::None)?;
+    fs::read(file).map_err(|_|ResponseStatus::Error)
+}
+/// Downloads definition and all of its textures in to the local assets, texture paths are
+/// changed to point in to the local assets root.
+fn download(addres: &String, kind: &str, id: String, target: String, assets: &String) -> bool {
+    let client = reqwest::blocking::Client::new();
+    let get = |path: String| -> Option<Vec<u8>> {
+        let response = client.get(format!("http://{addres}/assets/{path}")).send().ok()?;
+        if !response.status().is_success() {
+            return None;
+        }
+        Some(response.bytes().ok()?.to_vec())
+    };
+    let Some(bytes) = get(format!("{kind}/{id}")) else {
+        return false;
+    };
+    let Ok(mut definition) = serde_json::from_slice::<Value>(&bytes) else {
+        return false;
+    };
+    let downloaded = texture_paths(&mut definition,&mut |path|{
+        let Some(relative) = relative_path(path) else {
+            return false;
+        };
+        let local = Path::new(assets).join(&relative);
+        if !local.is_file() {
+            let Some(texture) = get(format!("textures/{path}")) else {
+                return false;
+            };
+            if let Some(parent) = local.parent() && fs::create_dir_all(parent).is_err() {
+                return false;
+            }
+            if fs::write(&local,texture).is_err() {
+                return false;
+            }
+        }
+        *path = local.to_string_lossy().to_string();
+        true
+    });
+    if !downloaded {
+        return false;
+    }
+    if let Some(parent) = Path::new(&target).parent() && fs::create_dir_all(parent).is_err() {
+        return false;
+    }
+    serde_json::to_vec_pretty(&definition).is_ok_and(|bytes|fs::write(&target,bytes).is_ok())
+}
+/// Downloads character the local assets dont have, returns if it succeeded.
+pub fn download_character(addres: &String, id: u32, assets: &String) -> bool {
+    download(addres,"characters",id.to_string(),Character::path(id,assets),assets)
+}
+/// Downloads map the local assets dont have, returns if it succeeded.
+pub fn download_map(addres: &String, id: usize, assets: &String) -> bool {
+    download(addres,"maps",id.to_string(),MapInformation::path(id,assets),assets)
+}
+/// Downloads every character and map the server has and the local assets dont, local files that
+/// are different from the server are not overwritten. Returns if anything new was downloaded.
+pub fn download_missing(addres: &String, mismatch: &AssetMismatch, local: &AssetManifest, assets: &String) -> bool {
+    let characters = mismatch.characters.iter()
+        .filter(|id|!local.characters.contains_key(id))
+        .filter(|id|download_character(addres,**id,assets))
+        .count();
+    let maps = mismatch.maps.iter()
+        .filter(|id|!local.maps.contains_key(id))
+        .filter(|id|download_map(addres,**id,assets))
+        .count();
+    characters + maps > 0
+}
@@ -21,6 +21,7 @@ use serde::{
 use crate::{
     base::Math,
     base::sircle::*,
+    base::bytes::*,
     game::physic::*,
     client::renderer::*,
 };
@@ -108,6 +109,10 @@ impl Character {
     }
 
     const CHAR_PATH: &str = "characters/";
+    /// File the character is defined in.
+    pub fn path(char_id: u32, assets: &String) -> String {
+        format!("{assets}{0}{char_id}.json",Self::CHAR_PATH)
+    }
     /// Tryes to load character from a file format it and load it to memory and GPU if possible.
     pub fn load(char_id: u32,display_option: &mut Option<&mut Display<WindowSurface>>, assets: &String) -> Option<Character> {
         if char_id == 0 {
@@ -118,7 +123,7 @@ impl Character {
             return Some(default);
         }
         let mut character_json = String::new();
-        let path = format!("{assets}{0}{char_id}.json",Self::CHAR_PATH);
+        let path = Self::path(char_id,assets);
         if let Ok(mut file) = File::open(path) && let Ok(_) = file.read_to_string(&mut character_json){
             let char_result = serde_json::from_str::<Self>(&character_json);
             match char_result {
@@ -236,6 +241,53 @@ impl CharacterInstance {
             last_input: CharacterInput::new(),
         }
     }
+    /// Writes everything that is send with a map snapshot, inputs are not included.
+    pub fn write_bytes(&self, out: &mut ByteWriter) {
+        out.u32(self.character);
+        out.u32(self.object_id);
+
+        out.vec2(&self.position);
+        out.vec2(&self.velocity);
+        out.u8(self.direction.to_byte());
+
+        out.bool(self.airborn);
+        out.u8(self.air_jump);
+        out.u8(self.air_action);
+
+        out.u8(self.vournable.to_byte());
+        self.state.write_bytes(out);
+        out.f32(self.damage);
+
+        out.u8(self.animation.to_byte());
+        out.u8(self.animation_hold);
+        out.u32(self.animation_frame as u32);
+    }
+    /// Reads instance written by write_bytes.
+    pub fn read_bytes(input: &mut ByteReader) -> Option<CharacterInstance> {
+        Some(CharacterInstance {
+            character: input.u32()?,
+            object_id: input.u32()?,
+
+            position: input.vec2()?,
+            velocity: input.vec2()?,
+            direction: Direction::from_byte(input.u8()?)?,
+
+            airborn: input.bool()?,
+            air_jump: input.u8()?,
+            air_action: input.u8()?,
+
+            vournable: ColisionState::from_byte(input.u8()?)?,
+            state: State::read_bytes(input)?,
+            damage: input.f32()?,
+
+            animation: AnimationState::from_byte(input.u8()?)?,
+            animation_hold: input.u8()?,
+            animation_frame: input.u32()? as usize,
+
+            input: CharacterInput::new(),
+            last_input: CharacterInput::new(),
+        })
+    }
     /// "Respawns" the character at world spawn.
     pub fn reset(&mut self) {
         *self = Self::new(self.character, self.object_id);
@@ -244,7 +296,7 @@ impl CharacterInstance {
         let mut colider = char_sheet.colider.clone();
         colider.position = Math::add_vec(&self.position,&colider.position);
 
-        if Math::distance(&colider.position, &col.position) > col.size/2.0 + colider.radius {
+        if !Math::within(&colider.position, &col.position, col.size/2.0 + colider.radius) {
             return false;
         }
         match col.rotation {
@@ -363,7 +415,7 @@ impl CharacterInstance {
         }
 
         let new_location = Math::add_vec(&self.position,&self.velocity);
-        if Math::distance(&new_location,&self.position) < 0.0001 {
+        if Math::distance_squared(&new_location,&self.position) < 0.0001*0.0001 {
             self.velocity = [0.0,0.0];
         } else {
             self.position = new_location;
@@ -429,7 +481,7 @@ impl CharacterInstance {
             State::Acting => {},
         }
 
-        if Math::distance(&self.position,&[0.0,0.0]) > 5.0 {
+        if !Math::within(&self.position,&[0.0,0.0],5.0) {
             self.reset();
         }
         self.last_input = self.input.clone();
@@ -563,3 +615,75 @@ impl CharacterInstance {
             .texture.draw_on(display, frame_display, position,&self.direction);
     }
 }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::map::Map;
+
+    /// Map with one instance for every variant of the encoded enums, the rest of the fields
+    /// changed from theyr defaults so a swapped field would show.
+    fn every_variant() -> Map {
+        let states = [State::Actionable,State::Acting,State::HitStun(7,(3,12))];
+        let animations = [
+            AnimationState::Damadged,
+            AnimationState::Idling,
+            AnimationState::Running,
+            AnimationState::Rizing,
+            AnimationState::Falling,
+            AnimationState::LightAttack,
+            AnimationState::HeavyAttack,
+            AnimationState::AirBornLightAttack,
+            AnimationState::AirBornHeavyAttack,
+        ];
+        let directions = [Direction::Left,Direction::Right];
+        let colisions = [ColisionState::Vulnerable,ColisionState::Invincible,ColisionState::UnTouchable];
+
+        let mut map = Map::new(2);
+        map.counter = 99;
+        for (index,animation) in animations.into_iter().enumerate() {
+            let id = map.new_istance(index as u32 + 1);
+            let instance = map.characters.get_mut(&id).unwrap();
+            instance.position = [index as f32 - 0.5,1.25];
+            instance.velocity = [-3.0,index as f32 * 0.1];
+            instance.direction = directions[index % directions.len()].clone();
+            instance.airborn = index % 2 == 0;
+            instance.air_jump = index as u8;
+            instance.air_action = 1;
+            instance.vournable = colisions[index % colisions.len()].clone();
+            instance.state = states[index % states.len()].clone();
+            instance.damage = 12.5 * index as f32;
+            instance.animation = animation;
+            instance.animation_hold = 4;
+            instance.animation_frame = index * 3;
+        }
+        map
+    }
+    #[test]
+    fn every_variant_round_trips() {
+        let map = every_variant();
+        let bytes = map.as_bytes();
+        let decoded = Map::from_bytes(&bytes).expect("Snapshot didnt decode");
+
+        assert_eq!(decoded.as_bytes(),bytes);
+        assert_eq!(decoded.counter,map.counter);
+        assert_eq!(decoded.map_id,map.map_id);
+        assert_eq!(decoded.characters.len(),map.characters.len());
+        for (id,instance) in &map.characters {
+            let other = &decoded.characters[id];
+            assert_eq!(other.character,instance.character);
+            assert_eq!(other.object_id,instance.object_id);
+            assert_eq!(other.position,instance.position);
+            assert_eq!(other.velocity,instance.velocity);
+            assert_eq!(other.direction,instance.direction);
+            assert_eq!(other.airborn,instance.airborn);
+            assert_eq!(other.air_jump,instance.air_jump);
+            assert_eq!(other.air_action,instance.air_action);
+            assert_eq!(other.vournable.to_byte(),instance.vournable.to_byte());
+            assert_eq!(format!("{:?}",other.state),format!("{:?}",instance.state));
+            assert_eq!(other.damage,instance.damage);
+            assert!(other.animation == instance.animation);
+            assert_eq!(other.animation_hold,instance.animation_hold);
+            assert_eq!(other.animation_frame,instance.animation_frame);
+        }
+    }
+}
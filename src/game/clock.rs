@@ -0,0 +1,60 @@
+use crate::base::bytes::*;
+
+/// Client asking for the server time, payload of a ping packet.
+#[derive(Debug, Clone)]
+pub struct TimeRequest {
+    /// Session token, time is of the room the player is in.
+    pub token: String,
+    /// Microseconds on the client clock when the ping was send, returned in the pong.
+    pub client_time: u64,
+}
+impl TimeRequest {
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut out = ByteWriter::new();
+        out.string(&self.token);
+        out.u64(self.client_time);
+        out.into_bytes()
+    }
+    pub fn from_bytes(bytes: &[u8]) -> Option<TimeRequest> {
+        let mut input = ByteReader::new(bytes);
+        Some(TimeRequest {
+            token: input.string()?,
+            client_time: input.u64()?,
+        })
+    }
+}
+/// Server time when the ping came, payload of a pong packet.
+#[derive(Debug, Clone)]
+pub struct TimeResponse {
+    /// Copied from the ping.
+    pub client_time: u64,
+    /// Map.counter of the room.
+    pub tick: u32,
+    /// Part of the next tick that already passed, 0 to 1.
+    pub progress: f32,
+    /// Ticks per second of the server.
+    pub rate: f32,
+}
+impl TimeResponse {
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut out = ByteWriter::new();
+        out.u64(self.client_time);
+        out.u32(self.tick);
+        out.f32(self.progress);
+        out.f32(self.rate);
+        out.into_bytes()
+    }
+    pub fn from_bytes(bytes: &[u8]) -> Option<TimeResponse> {
+        let mut input = ByteReader::new(bytes);
+        Some(TimeResponse {
+            client_time: input.u64()?,
+            tick: input.u32()?,
+            progress: input.f32()?,
+            rate: input.f32()?,
+        })
+    }
+    /// Server tick with the part of the next one.
+    pub fn server_tick(&self) -> f64 {
+        self.tick as f64 + self.progress as f64
+    }
+}
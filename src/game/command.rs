@@ -0,0 +1,62 @@
+use std::net::{
+    IpAddr,
+    SocketAddr,
+};
+use std::sync::mpsc::{
+    channel,
+    Sender,
+    SyncSender,
+    TrySendError,
+};
+use crate::game::networking::*;
+use crate::game::transport::Packet;
+use crate::game::room::RoomInfo;
+
+/// Channel the simulation thread answers a command with.
+pub type Reply = Sender<Response>;
+
+/// Everything that changes the server state. Connection threads only check what they can without
+/// the rooms (passwords, assets) and send the rest to the simulation thread, which is the only one
+/// that owns the rooms and applies commands between ticks.
+pub enum Command {
+    Join(JoinRequest,Option<IpAddr>,Reply),
+    Spectate(SpectateRequest,Option<IpAddr>,Reply),
+    Leave(String,Reply),
+    Resume(String,Reply),
+    Chat(ChatRequest,Reply),
+    SwitchCharacter(Option<u32>,String,Reply),
+    CreateRoom(CreateRoomRequest,Reply),
+    /// Already authorized admin command.
+    Admin(AdminRequest,Reply),
+    /// Server metrics in Prometheus text format.
+    Metrics(Reply),
+    /// Answered http request, counted in metrics.
+    Served {
+        method: String,
+        path: String,
+        status: u16,
+        bytes: usize,
+    },
+    /// Packet that came over UDP, answers and relayed inputs are send by the simulation thread.
+    Packet(Packet,SocketAddr),
+}
+impl Command {
+    /// Sends the command and waits for the answer, full queue is answered with 503 right away.
+    pub fn ask(commands: &SyncSender<Command>, command: impl FnOnce(Reply) -> Command) -> Response {
+        let (reply,answer) = channel();
+        match commands.try_send(command(reply)) {
+            Ok(()) => {},
+            Err(TrySendError::Full(_)) => return Response::error(ErrorCode::Busy),
+            Err(TrySendError::Disconnected(_)) => return Response::error(ErrorCode::ServerError),
+        }
+        answer.recv().unwrap_or_else(|_|Response::error(ErrorCode::ServerError))
+    }
+}
+/// State of the server published after every tick, read by connection threads without asking
+/// the simulation thread.
+#[derive(Default)]
+pub struct ServerState {
+    pub rooms: Vec<RoomInfo>,
+    /// Map of the first room.
+    pub map: Vec<u8>,
+}
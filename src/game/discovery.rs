@@ -0,0 +1,80 @@
+use std::{
+    net::{
+        SocketAddr,
+        UdpSocket,
+    },
+    time::{
+        Duration,
+        Instant,
+    },
+};
+use crate::base::bytes::*;
+use crate::game::transport::*;
+
+/// What a server tells about itself when it is found on the local network.
+#[derive(Debug, Clone)]
+pub struct ServerInfo {
+    pub name: String,
+    /// Map of the first room.
+    pub map_id: usize,
+    pub players: u32,
+    pub rooms: u32,
+    /// If the first room needs a password.
+    pub password: bool,
+}
+impl ServerInfo {
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut out = ByteWriter::new();
+        out.string(&self.name);
+        out.u64(self.map_id as u64);
+        out.u32(self.players);
+        out.u32(self.rooms);
+        out.bool(self.password);
+        out.into_bytes()
+    }
+    pub fn from_bytes(bytes: &[u8]) -> Option<ServerInfo> {
+        let mut input = ByteReader::new(bytes);
+        Some(ServerInfo {
+            name: input.string()?,
+            map_id: input.u64()? as usize,
+            players: input.u32()?,
+            rooms: input.u32()?,
+            password: input.bool()?,
+        })
+    }
+}
+/// Broadcasts a probe on the local network and collects answers of all servers listening on the
+/// port. Servers have to be bound to 0.0.0.0 (or LAN addres) to be found.
+pub fn discover(port: u16, wait: Duration) -> Vec<(SocketAddr,ServerInfo)> {
+    let mut out: Vec<(SocketAddr,ServerInfo)> = Vec::new();
+    let Ok(socket) = UdpSocket::bind("0.0.0.0:0") else {
+        return out;
+    };
+    if socket.set_broadcast(true).is_err() {
+        return out;
+    }
+    let probe = Packet::new(PacketKind::Probe,0,0,Vec::new());
+    probe.send_to(&socket,&SocketAddr::from(([255,255,255,255],port)));
+    // Servers on this machine dont always get the broadcast
+    probe.send_to(&socket,&SocketAddr::from(([127,0,0,1],port)));
+
+    let end = Instant::now() + wait;
+    while let Some(left) = end.checked_duration_since(Instant::now()) && !left.is_zero() {
+        if socket.set_read_timeout(Some(left)).is_err() {
+            break;
+        }
+        let Some((packet,addres)) = Packet::receive(&socket) else {
+            break;
+        };
+        if packet.kind == PacketKind::Info && let Some(info) = ServerInfo::from_bytes(&packet.payload) {
+            out.push((addres,info));
+        }
+    }
+    // Local server answers both probes, its LAN addres is more usefull
+    let lan: Vec<(u16,String)> = out.iter()
+        .filter(|(addres,_)|!addres.ip().is_loopback())
+        .map(|(addres,info)|(addres.port(),info.name.clone()))
+        .collect();
+    out.retain(|(addres,info)|!addres.ip().is_loopback() || !lan.contains(&(addres.port(),info.name.clone())));
+    out
+}
@@ -0,0 +1,81 @@
+use serde::{
+    Serialize,
+    Deserialize,
+};
+use std::{
+    collections::{
+        BTreeMap,
+        HashMap,
+    },
+    fs,
+};
+use crate::game::assets::definition_checksum;
+use crate::game::networking::json_display;
+use crate::game::{
+    character::Character,
+    map::MapInformation,
+};
+
+/// Checksum of every character and map definition, client and server with different definitions
+/// would disagree on hitboxes and colisions. Textures are not part of it.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AssetManifest {
+    pub characters: BTreeMap<u32,u64>,
+    pub maps: BTreeMap<usize,u64>,
+}
+/// Ids that are different or missing on one side.
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct AssetMismatch {
+    /// Protocol version of the server.
+    pub protocol: u32,
+    pub characters: Vec<u32>,
+    pub maps: Vec<usize>,
+}
+impl AssetMismatch {
+    pub fn from_string(input: &str) -> Option<Self> {
+        serde_json::from_str::<Self>(input).ok()
+    }
+    /// Text that can be shown to the player.
+    pub fn message(&self) -> String {
+        let mut out = String::new();
+        if self.protocol != crate::game::networking::PROTOCOL_VERSION {
+            out.push_str(&format!("Server uses protocol version {0}, client uses {1}. ",self.protocol,crate::game::networking::PROTOCOL_VERSION));
+        }
+        if !self.characters.is_empty() {
+            out.push_str(&format!("Different characters: {:?}. ",self.characters));
+        }
+        if !self.maps.is_empty() {
+            out.push_str(&format!("Different maps: {:?}.",self.maps));
+        }
+        out
+    }
+}
+json_display!(AssetMismatch);
+impl AssetManifest {
+    /// Checksums of definition files of every loaded character and map, built in ones (id 0)
+    /// have checksum 0.
+    pub fn new(characters: &HashMap<u32,Character>, maps: &HashMap<usize,MapInformation>, assets: &String) -> AssetManifest {
+        let file_checksum = |path: String| fs::read(path).map_or(0,|bytes|definition_checksum(&bytes));
+        AssetManifest {
+            characters: characters.keys()
+                .map(|id|(*id,if *id == 0 {0} else {file_checksum(Character::path(*id,assets))}))
+                .collect(),
+            maps: maps.keys()
+                .map(|id|(*id,if *id == 0 {0} else {file_checksum(MapInformation::path(*id,assets))}))
+                .collect(),
+        }
+    }
+    /// Ids that differ between the two manifests.
+    pub fn compare(&self, other: &AssetManifest) -> (Vec<u32>,Vec<usize>) {
+        fn differ<K: Ord + Copy>(a: &BTreeMap<K,u64>, b: &BTreeMap<K,u64>) -> Vec<K> {
+            let mut out: Vec<K> = a.iter()
+                .filter(|(id,sum)|b.get(id) != Some(sum))
+                .map(|(id,_)|*id)
+                .collect();
+            out.extend(b.keys().filter(|id|!a.contains_key(id)));
+            out.sort();
+            out
+        }
+        (differ(&self.characters,&other.characters),differ(&self.maps,&other.maps))
+    }
+}
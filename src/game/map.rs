@@ -4,18 +4,23 @@ use serde::{
 };
 use crate::game::{
     character::CharacterInstance,
+    networking::CharacterInput,
     Character,
 
     physic::Direction,
     physic::ColisionPlane,
 };
 use crate::client::renderer::Texture;
+use crate::base::bytes::*;
 use glium::{
     glutin::surface::WindowSurface,
     Display,
 };
 use std::{
-    collections::HashMap,
+    collections::{
+        BTreeMap,
+        HashMap,
+    },
     fs::{
         self,
         File,
@@ -69,6 +74,10 @@ impl MapInformation {
         serde_json::to_string(self).unwrap()
     }
     const MAP_PATH: &str = "maps/";
+    /// File the map is defined in.
+    pub fn path(map_id: usize, assets: &String) -> String {
+        format!("{assets}{0}{map_id}.json",Self::MAP_PATH)
+    }
     /// Tryes load file with correct id, parses the map and (if possible) loads textures on to GPU.
     pub fn load(map_id: usize, display_option: &mut Option<&mut Display<WindowSurface>>, assets: &String) -> Option<MapInformation> {
         if map_id == 0 {
@@ -79,7 +88,7 @@ impl MapInformation {
             return Some(default);
         }
         let mut character_json = String::new();
-        if let Ok(mut file) = File::open(format!("{assets}{0}{map_id}.json",Self::MAP_PATH)) && let Ok(_) = file.read_to_string(&mut character_json){
+        if let Ok(mut file) = File::open(Self::path(map_id,assets)) && let Ok(_) = file.read_to_string(&mut character_json){
             let char_result = serde_json::from_str::<Self>(&character_json);
             match char_result {
                 Ok(mut output) => {
@@ -121,6 +130,64 @@ impl MapInformation {
         out
     }
 }
+/// Something that happend on the server that clients should know about, send with snapshots.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum ServerEvent {
+    /// Player with this name joined the room.
+    Joined(String),
+    /// Player with this name left or was removed for inactivity.
+    PlayerLeft(String),
+    /// Player with this name was kicked out by an admin.
+    Kicked(String),
+    /// Admin paused the match.
+    Paused,
+    Resumed,
+    /// Chat message, name of the player and the text.
+    Chat(String,String),
+    /// Player stopped sending packets, his character is gone until he reconnects.
+    Disconnected(String),
+    Reconnected(String),
+}
+impl ServerEvent {
+    /// Text that can be shown to the players.
+    pub fn message(&self) -> String {
+        match self {
+            ServerEvent::PlayerLeft(name) => format!("{name} left the game"),
+            ServerEvent::Kicked(name) => format!("{name} was kicked"),
+            ServerEvent::Paused => String::from("Game paused"),
+            ServerEvent::Resumed => String::from("Game resumed"),
+            ServerEvent::Chat(name,text) => format!("{name}: {text}"),
+            ServerEvent::Disconnected(name) => format!("{name} lost connection"),
+            ServerEvent::Reconnected(name) => format!("{name} reconnected"),
+            ServerEvent::Joined(name) => format!("{name} joined the game"),
+        }
+    }
+    pub fn write_bytes(&self, out: &mut ByteWriter) {
+        match self {
+            ServerEvent::PlayerLeft(name) => {out.u8(0); out.string(name);},
+            ServerEvent::Kicked(name) => {out.u8(1); out.string(name);},
+            ServerEvent::Paused => out.u8(2),
+            ServerEvent::Resumed => out.u8(3),
+            ServerEvent::Chat(name,text) => {out.u8(4); out.string(name); out.string(text);},
+            ServerEvent::Disconnected(name) => {out.u8(5); out.string(name);},
+            ServerEvent::Reconnected(name) => {out.u8(6); out.string(name);},
+            ServerEvent::Joined(name) => {out.u8(7); out.string(name);},
+        }
+    }
+    pub fn read_bytes(input: &mut ByteReader) -> Option<ServerEvent> {
+        match input.u8()? {
+            0 => Some(ServerEvent::PlayerLeft(input.string()?)),
+            1 => Some(ServerEvent::Kicked(input.string()?)),
+            2 => Some(ServerEvent::Paused),
+            3 => Some(ServerEvent::Resumed),
+            4 => Some(ServerEvent::Chat(input.string()?,input.string()?)),
+            5 => Some(ServerEvent::Disconnected(input.string()?)),
+            6 => Some(ServerEvent::Reconnected(input.string()?)),
+            7 => Some(ServerEvent::Joined(input.string()?)),
+            _ => Option::None,
+        }
+    }
+}
 /// Instance of a map, doesnt contain any information about how the map looks but information that
 /// is assencial for correctly working game. List of characters, reference to map definition,
 /// currently heighest ID 
@@ -129,16 +196,101 @@ pub struct Map {
     pub counter: usize,
     #[serde(skip_serializing,skip_deserializing)]
     pub current_id: u32,
-    pub characters: HashMap<u32,CharacterInstance>,
+    /// Ordered by id so every machine updates characters in the same order.
+    pub characters: BTreeMap<u32,CharacterInstance>,
     pub map_id: usize,
+    /// Last few events with theyr id, clients show only events with id they didnt see yet.
+    #[serde(default)]
+    pub events: Vec<(u32,ServerEvent)>,
 }
 impl Map {
-    pub fn from_bytes(_stream: &[u8]) -> Option<Self> {
-        todo!();
+    /// Version of the binary snapshot, has to be changed with every change of the format.
+    const BYTES_VERSION: u8 = 3;
+    /// How many events are kept, snapshots can be lost so events are repeated in a few of them.
+    const MAX_EVENTS: usize = 16;
+    /// Parses snapshot made by as_bytes, returns None if the snapshot is broken or has different
+    /// version.
+    pub fn from_bytes(stream: &[u8]) -> Option<Self> {
+        let mut input = ByteReader::new(stream);
+        if input.u8()? != Self::BYTES_VERSION {
+            return Option::None;
+        }
+        let counter = input.u64()? as usize;
+        let map_id = input.u64()? as usize;
+
+        let count = input.u32()?;
+        let mut characters = BTreeMap::new();
+        for _ in 0..count {
+            let id = input.u32()?;
+            characters.insert(id,CharacterInstance::read_bytes(&mut input)?);
+        }
+        let count = input.u32()?;
+        let mut events = Vec::new();
+        for _ in 0..count {
+            events.push((input.u32()?,ServerEvent::read_bytes(&mut input)?));
+        }
+        if !input.is_empty() {
+            return Option::None;
+        }
+        Some(Map {
+            counter,
+            current_id: 0,
+            characters,
+            map_id,
+            events,
+        })
     }
+    /// Compact snapshot of the map that is send to the clients.
+    ///
+    /// version: u8, counter: u64, map_id: u64, count: u32, (id: u32, instance) * count,
+    /// count: u32, (id: u32, event) * count
     pub fn as_bytes(&self) -> Vec<u8> {
-        let mut _out: Vec<u8> = Vec::new();
-        todo!();
+        let mut out = ByteWriter::new();
+        out.u8(Self::BYTES_VERSION);
+        out.u64(self.counter as u64);
+        out.u64(self.map_id as u64);
+
+        out.u32(self.characters.len() as u32);
+        for (id,instance) in &self.characters {
+            out.u32(*id);
+            instance.write_bytes(&mut out);
+        }
+        out.u32(self.events.len() as u32);
+        for (id,event) in &self.events {
+            out.u32(*id);
+            event.write_bytes(&mut out);
+        }
+        out.into_bytes()
+    }
+    /// Adds event for the clients, only the newest few are kept.
+    pub fn push_event(&mut self, event: ServerEvent) {
+        let id = self.events.last().map_or(1,|(id,_)|id + 1);
+        self.events.push((id,event));
+        if self.events.len() > Self::MAX_EVENTS {
+            self.events.remove(0);
+        }
+    }
+    /// Removes character of a player that left and tells everyone about it.
+    pub fn remove_player(&mut self, name: &str, instance: Option<u32>) {
+        self.remove_instance(instance);
+        self.push_event(ServerEvent::PlayerLeft(name.to_string()));
+    }
+    pub fn remove_instance(&mut self, instance: Option<u32>) {
+        if let Some(id) = instance {
+            self.characters.remove(&id);
+        }
+    }
+    /// Switches to another map, all characters start again.
+    pub fn change_map(&mut self, map_id: usize) {
+        self.map_id = map_id;
+        for instance in self.characters.values_mut() {
+            instance.reset();
+        }
+    }
+    /// Hash of the whole snapshot, if two machines have different checksum for the same tick
+    /// theyr simulations desynced.
+    pub fn checksum(&self) -> u64 {
+        checksum(&self.as_bytes())
     }
     /// Creates new character with its own ID, this ID is returned
     pub fn new_istance(&mut self, character: u32) -> u32 {
@@ -153,17 +305,19 @@ impl Map {
     pub fn new(map_id: usize) -> Map {
         Map {
             counter: 0,
-            characters: HashMap::new(),
+            characters: BTreeMap::new(),
             current_id: 0,
             map_id,
+            events: Vec::new(),
         }
     }
     pub fn test() -> Map {
         Map {
             counter: 0,
-            characters: HashMap::new(),
+            characters: BTreeMap::new(),
             current_id: 0,
             map_id: 0,
+            events: Vec::new(),
         }
     }
     /// Copy inputs from players to theyr given characters.
@@ -174,10 +328,27 @@ impl Map {
             }
         }
     }
+    /// Copy inputs to characters by theyr instance id.
+    pub fn set_instance_inputs(&mut self,inputs: &HashMap<u32,CharacterInput>) {
+        for (id,input) in inputs {
+            if let Some(instance) = self.characters.get_mut(id) {
+                instance.input = input.clone();
+            }
+        }
+    }
+    /// Current input of every instance.
+    pub fn inputs(&self) -> HashMap<u32,CharacterInput> {
+        self.characters.iter().map(|(id,instance)|(*id,instance.input.clone())).collect()
+    }
+    /// Every instance with its character, sorted by instance id.
+    pub fn roster(&self) -> Vec<(u32,u32)> {
+        self.characters.iter().map(|(id,instance)|(*id,instance.character)).collect()
+    }
     /// Runs trhought objects on map and updates theyr colision and update sicles.
     pub fn update(&mut self, char_sheet: &HashMap<u32,Character>,map_pool: &HashMap<usize,MapInformation>, delta: &f32) {
         if let Some(map) = map_pool.get(&self.map_id) {
-            // Hitbox check
+            // Hitbox check, every character is hit in order of theyr id against the state from
+            // before this tick so the result doesnt depend on the order.
             let static_enemies = self.characters.clone();
             for (main,player) in &mut self.characters.iter_mut() {
                 for (secondary,enemy) in static_enemies.iter() {
@@ -196,3 +367,85 @@ impl Map {
         }
     }
 }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot() -> Vec<u8> {
+        let mut map = Map::new(1);
+        map.new_istance(1);
+        map.new_istance(2);
+        map.push_event(ServerEvent::PlayerLeft(String::from("left")));
+        map.push_event(ServerEvent::Kicked(String::from("kicked")));
+        map.push_event(ServerEvent::Paused);
+        map.push_event(ServerEvent::Resumed);
+        map.push_event(ServerEvent::Chat(String::from("name"),String::from("text")));
+        map.push_event(ServerEvent::Disconnected(String::from("gone")));
+        map.push_event(ServerEvent::Reconnected(String::from("back")));
+        map.push_event(ServerEvent::Joined(String::from("new")));
+        map.as_bytes()
+    }
+    #[test]
+    fn events_round_trip() {
+        let bytes = snapshot();
+        let map = Map::from_bytes(&bytes).expect("Snapshot didnt decode");
+        assert_eq!(map.events.len(),8);
+        assert_eq!(map.as_bytes(),bytes);
+    }
+    #[test]
+    fn wrong_version_is_rejected() {
+        let mut bytes = snapshot();
+        bytes[0] = Map::BYTES_VERSION.wrapping_add(1);
+        assert!(Map::from_bytes(&bytes).is_none());
+    }
+    #[test]
+    fn truncated_snapshot_is_rejected() {
+        let bytes = snapshot();
+        for length in 0..bytes.len() {
+            assert!(Map::from_bytes(&bytes[..length]).is_none(),"{length} bytes were accepted");
+        }
+    }
+    /// Scripted inputs, the same for every run.
+    fn input(tick: usize, instance: u32) -> CharacterInput {
+        let phase = (tick / 7 + instance as usize) % 6;
+        CharacterInput {
+            dir: match phase {
+                0 | 1 => Some(Direction::Left),
+                3 | 4 => Some(Direction::Right),
+                _ => None,
+            },
+            light_attack: phase == 2 && tick.is_multiple_of(3),
+            heavy_attack: phase == 5 && tick.is_multiple_of(4),
+            special: false,
+            jump: tick % 23 == instance as usize,
+            down: phase == 1 && tick.is_multiple_of(2),
+        }
+    }
+    fn simulate(ticks: usize) -> Map {
+        let assets = String::from("./assets/");
+        let characters = Character::load_all(None,&assets);
+        let map_pool = MapInformation::load_all(None,&assets);
+        let mut map = Map::new(1);
+        let ids = [map.new_istance(1),map.new_istance(2),map.new_istance(3)];
+        for tick in 0..ticks {
+            map.counter += 1;
+            let inputs = ids.iter().map(|id|(*id,input(tick,*id))).collect();
+            map.set_instance_inputs(&inputs);
+            map.update(&characters,&map_pool,&(1.0/60.0));
+        }
+        map
+    }
+    #[test]
+    fn same_inputs_give_the_same_map() {
+        let (first,second) = (simulate(600),simulate(600));
+        assert_eq!(first.as_bytes(),second.as_bytes());
+        assert_eq!(first.checksum(),second.checksum());
+        assert_ne!(first.checksum(),Map::new(1).checksum());
+    }
+    #[test]
+    fn trailing_bytes_are_rejected() {
+        let mut bytes = snapshot();
+        bytes.push(0);
+        assert!(Map::from_bytes(&bytes).is_none());
+    }
+}
@@ -0,0 +1,103 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    time::Duration,
+};
+
+/// Counters of the running server, shown in Prometheus text format on GET /metrics.
+#[derive(Default)]
+pub struct Metrics {
+    ticks: u64,
+    /// Time spent in all ticks together.
+    tick_seconds: f64,
+    last_tick_seconds: f64,
+    /// Ticks that took longer then one frame.
+    tick_overruns: u64,
+
+    rooms: usize,
+    players: usize,
+    spectators: usize,
+    instances: usize,
+
+    /// (method, endpoint, status code) -> count
+    requests: BTreeMap<(String,String,u16),u64>,
+    /// Bytes send over http and over UDP.
+    http_bytes: u64,
+    udp_bytes: u64,
+}
+impl Metrics {
+    /// Endpoints that are counted by theyr path, everything else is counted as "other".
+    /// Downloaded assets are counted together as "/assets/".
+    const ENDPOINTS: [&str;9] = ["/","/rooms/","/map/","/character/","/spectate/","/admin/","/chat/","/resume/","/metrics"];
+    /// Methods the server takes, any other is counted as "other" so clients cant add labels.
+    const METHODS: [&str;4] = ["GET","POST","PUT","DELETE"];
+    pub fn new() -> Metrics {
+        Self::default()
+    }
+    pub fn tick(&mut self, duration: Duration, overrun: bool) {
+        self.ticks += 1;
+        self.tick_seconds += duration.as_secs_f64();
+        self.last_tick_seconds = duration.as_secs_f64();
+        if overrun {
+            self.tick_overruns += 1;
+        }
+    }
+    /// Current number of everything connected to the server.
+    pub fn population(&mut self, rooms: usize, players: usize, spectators: usize, instances: usize) {
+        self.rooms = rooms;
+        self.players = players;
+        self.spectators = spectators;
+        self.instances = instances;
+    }
+    pub fn request(&mut self, method: &str, path: &str, status: u16, bytes: usize) {
+        let method = if Self::METHODS.contains(&method) {method} else {"other"};
+        let (method,endpoint) = if Self::ENDPOINTS.contains(&path) {
+            (method,path)
+        } else if path.starts_with("/assets/") {
+            (method,"/assets/")
+        } else {
+            ("other","other")
+        };
+        *self.requests.entry((method.to_string(),endpoint.to_string(),status)).or_default() += 1;
+        self.http_bytes += bytes as u64;
+    }
+    pub fn udp_sent(&mut self, bytes: usize) {
+        self.udp_bytes += bytes as u64;
+    }
+    /// Prometheus text format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out,"# HELP nebula_tick_duration_seconds Time spent simulating and sending one tick.");
+        let _ = writeln!(out,"# TYPE nebula_tick_duration_seconds summary");
+        let _ = writeln!(out,"nebula_tick_duration_seconds_sum {}",self.tick_seconds);
+        let _ = writeln!(out,"nebula_tick_duration_seconds_count {}",self.ticks);
+        let _ = writeln!(out,"# HELP nebula_last_tick_duration_seconds Duration of the last tick.");
+        let _ = writeln!(out,"# TYPE nebula_last_tick_duration_seconds gauge");
+        let _ = writeln!(out,"nebula_last_tick_duration_seconds {}",self.last_tick_seconds);
+        let _ = writeln!(out,"# HELP nebula_tick_overruns_total Ticks that took longer then the frame time.");
+        let _ = writeln!(out,"# TYPE nebula_tick_overruns_total counter");
+        let _ = writeln!(out,"nebula_tick_overruns_total {}",self.tick_overruns);
+
+        for (name,help,value) in [
+            ("nebula_rooms","Rooms on the server.",self.rooms),
+            ("nebula_players","Connected players.",self.players),
+            ("nebula_spectators","Connected spectators.",self.spectators),
+            ("nebula_character_instances","Active character instances.",self.instances),
+        ] {
+            let _ = writeln!(out,"# HELP {name} {help}");
+            let _ = writeln!(out,"# TYPE {name} gauge");
+            let _ = writeln!(out,"{name} {value}");
+        }
+
+        let _ = writeln!(out,"# HELP nebula_http_requests_total Http requests by endpoint and status code.");
+        let _ = writeln!(out,"# TYPE nebula_http_requests_total counter");
+        for ((method,endpoint,status),count) in &self.requests {
+            let _ = writeln!(out,"nebula_http_requests_total{{method=\"{method}\",endpoint=\"{endpoint}\",status=\"{status}\"}} {count}");
+        }
+        let _ = writeln!(out,"# HELP nebula_bytes_sent_total Bytes send to clients.");
+        let _ = writeln!(out,"# TYPE nebula_bytes_sent_total counter");
+        let _ = writeln!(out,"nebula_bytes_sent_total{{protocol=\"http\"}} {}",self.http_bytes);
+        let _ = writeln!(out,"nebula_bytes_sent_total{{protocol=\"udp\"}} {}",self.udp_bytes);
+        out
+    }
+}
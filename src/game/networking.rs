@@ -1,13 +1,69 @@
-use chrono::{Utc,DateTime,Datelike};
-use std::net::TcpStream;
+use chrono::{Utc,DateTime};
 use crate::game::physic::Direction;
+use crate::base::bytes::*;
+use crate::game::manifest::AssetManifest;
 use serde::{
     Serialize,
     Deserialize,
 };
-use std::io::Read;
+use std::io::{
+    BufRead,
+    Read,
+};
+use std::net::TcpStream;
+use std::time::Instant;
+
+/// Version of the network protocol, has to be changed with every incompatible change of
+/// requests, packets or snapshots.
+pub const PROTOCOL_VERSION: u32 = 5;
+
+/// Display of a request or response as its JSON body, it is what to_string sends.
+macro_rules! json_display {
+    ($($name:ty),* $(,)?) => {$(
+        impl std::fmt::Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str(&serde_json::to_string(self).map_err(|_|std::fmt::Error)?)
+            }
+        }
+    )*};
+}
+pub(crate) use json_display;
+
+json_display!(
+    ErrorResponse,
+    CharacterInput,
+    GameControlPacket,
+    CharacterSwitchRequest,
+    JoinRequest,
+    JoinResponse,
+    SpectateRequest,
+    CreateRoomRequest,
+    AdminRequest,
+    LeaveRequest,
+    ResumeRequest,
+    ChatRequest,
+);
 
-/// List of header that can any request have or has to have to work.
+/// Largest request body that is read, bigger requests are refused with 413.
+pub const MAX_BODY_LENGTH: usize = 64*1024;
+/// Longest request line or header line.
+const MAX_LINE_LENGTH: usize = 8*1024;
+/// Most header lines in one request.
+const MAX_HEADER_COUNT: usize = 64;
+
+/// Why a request couldnt be read.
+#[derive(Debug)]
+pub enum RequestError {
+    /// Connection was closed or timed out before a whole request came, nothing is answered.
+    Closed,
+    /// Request isnt valid http.
+    Malformed,
+    /// Body is longer then MAX_BODY_LENGTH.
+    TooLarge,
+    /// Body uses a transfer encoding, only Content-Length is supported.
+    Unsupported,
+}
+/// Request line and headers of a http request.
 #[derive(Debug)]
 pub struct Headers {
     pub request_type: String,
@@ -17,6 +73,9 @@ pub struct Headers {
     pub content_type: Option<String>,
     pub user_agent: Option<String>,
     pub body_length: usize,
+    /// Connection stays open for more requests, default for HTTP/1.1.
+    pub keep_alive: bool,
+    pub transfer_encoding: Option<String>,
 }
 impl Headers {
     pub fn none() -> Headers {
@@ -27,60 +86,132 @@ impl Headers {
             content_type: None,
             user_agent: None,
             body_length: 0,
+            keep_alive: false,
+            transfer_encoding: None,
         }
     }
-    /// Parsing headers from new incoming mesagge.
-    pub fn new(stream: &mut TcpStream) -> Headers {
+    /// Reads request line and headers of the next request on the connection.
+    pub fn new(stream: &mut impl BufRead) -> Result<Headers,RequestError> {
         let mut out = Self::none();
-        if let Some(line) = get_line(stream) {
-            let rq_line: Vec<String> = line.split_whitespace().map(|x|String::from(x)).collect();
-            if let Some(word) = rq_line.get(0) {
-                out.request_type = word.to_string();
-            } 
-            if let Some(word) = rq_line.get(1) {
-                out.path = word.to_string();
+        // Empty lines before a request are allowed
+        let line = loop {
+            let line = get_line(stream)?;
+            if !line.is_empty() {
+                break line;
             }
-            out.http_type = rq_line.get(2).cloned();
+        };
+        let mut words = line.split(' ');
+        let (Some(method),Some(target),Some(version),None) = (words.next(),words.next(),words.next(),words.next()) else {
+            return Err(RequestError::Malformed);
+        };
+        if method.is_empty() || !target.starts_with('/') || !version.starts_with("HTTP/1.") {
+            return Err(RequestError::Malformed);
         }
-        while let Some(line) = get_line(stream) && line.len() > 1 {
-            let rq_line: Vec<String> = line.split_whitespace().map(|x|String::from(x)).collect();
+        out.request_type = method.to_string();
+        // Query isnt used by any endpoint
+        out.path = target.split('?').next().unwrap_or(target).to_string();
+        out.keep_alive = version != "HTTP/1.0";
+        out.http_type = Some(version.to_string());
 
-
-            if let Some(word) = rq_line.get(0) {
-                match word.as_str() {
-                    "Content-Type:" | "content-type:" => {out.content_type = rq_line.get(1).cloned();}
-                    "Content-Length:" | "content-length:"=> {
-                        if let Some(word) = rq_line.get(1) && let Ok(number) = word.parse::<usize>(){
-                            out.body_length = number+1;
-                        }
+        let mut count = 0;
+        loop {
+            let line = get_line(stream)?;
+            if line.is_empty() {
+                return Ok(out);
+            }
+            count += 1;
+            if count > MAX_HEADER_COUNT {
+                return Err(RequestError::Malformed);
+            }
+            let (name,value) = line.split_once(':').ok_or(RequestError::Malformed)?;
+            let value = value.trim();
+            match name.to_ascii_lowercase().as_str() {
+                "content-type" => out.content_type = Some(value.to_string()),
+                "user-agent" => out.user_agent = Some(value.to_string()),
+                "content-length" => out.body_length = value.parse().map_err(|_|RequestError::Malformed)?,
+                "transfer-encoding" => out.transfer_encoding = Some(value.to_string()),
+                "connection" => for option in value.split(',').map(|option|option.trim().to_ascii_lowercase()) {
+                    match option.as_str() {
+                        "close" => out.keep_alive = false,
+                        "keep-alive" => out.keep_alive = true,
+                        _ => {},
                     }
-                    _ => {},
-                }
+                },
+                _ => {},
             }
-
         }
-        out
     }
 }
-pub fn get_line(stream: &mut TcpStream) -> Option<String> {
-    let mut line = String::new();
-    let mut buffer = [0;1];
-    while let Ok(_) = stream.read_exact(&mut buffer) && let Ok(character) = str::from_utf8(&buffer) {
-        if buffer[0] == b'\n' {return Some(line);}
-        line.push_str(character)
+/// Reads one line without its line ending, CRLF and bare LF are both accepted.
+pub fn get_line(stream: &mut impl BufRead) -> Result<String,RequestError> {
+    let mut line = Vec::new();
+    let read = stream.take(MAX_LINE_LENGTH as u64 + 2)
+        .read_until(b'\n',&mut line)
+        .map_err(|_|RequestError::Closed)?;
+    if read == 0 {
+        return Err(RequestError::Closed);
+    }
+    if line.pop() != Some(b'\n') {
+        return Err(if read > MAX_LINE_LENGTH {RequestError::Malformed} else {RequestError::Closed});
     }
-    None
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    String::from_utf8(line).map_err(|_|RequestError::Malformed)
 }
-pub fn get_responce<T: for<'a> Deserialize<'a>>(stream: &mut TcpStream, headers: Headers) -> Option<T> {
-    if headers.body_length > 0 {
-        let mut buffer = vec![0;headers.body_length-1];
-        if let Ok(_) = stream.read_exact(&mut buffer) && let Ok(obj_str) = str::from_utf8(&buffer) {
-            if let Ok(output) = serde_json::from_str::<T>(obj_str) {
-                return Some(output);
-            }
+/// Connection that has to deliver the whole request before a deadline, a client sending one
+/// byte at a time cant keep it open longer.
+pub struct DeadlineStream {
+    stream: TcpStream,
+    deadline: Instant,
+}
+impl DeadlineStream {
+    pub fn new(stream: TcpStream) -> DeadlineStream {
+        DeadlineStream {
+            stream,
+            deadline: Instant::now(),
         }
     }
-    None
+    /// Deadline of the next request.
+    pub fn set_deadline(&mut self, deadline: Instant) {
+        self.deadline = deadline;
+    }
+}
+impl Read for DeadlineStream {
+    /// Every read waits only for the time that is left.
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let left = self.deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err(std::io::ErrorKind::TimedOut.into());
+        }
+        self.stream.set_read_timeout(Some(left))?;
+        self.stream.read(buf)
+    }
+}
+/// Http request with its whole body.
+#[derive(Debug)]
+pub struct Request {
+    pub headers: Headers,
+    pub body: Vec<u8>,
+}
+impl Request {
+    /// Reads the next request on the connection, body is read only if it isnt too large.
+    pub fn read(stream: &mut impl BufRead) -> Result<Request,RequestError> {
+        let headers = Headers::new(stream)?;
+        if headers.transfer_encoding.is_some() {
+            return Err(RequestError::Unsupported);
+        }
+        if headers.body_length > MAX_BODY_LENGTH {
+            return Err(RequestError::TooLarge);
+        }
+        let mut body = vec![0;headers.body_length];
+        stream.read_exact(&mut body).map_err(|_|RequestError::Closed)?;
+        Ok(Request {headers,body})
+    }
+    /// Body read as JSON.
+    pub fn json<T: for<'a> Deserialize<'a>>(&self) -> Option<T> {
+        serde_json::from_slice::<T>(&self.body).ok()
+    }
 }
 
 /// Statuses that can be returned on request
@@ -92,25 +223,181 @@ pub enum ResponseStatus {
     ParseError,
     None,
     Forbiden,
+    /// Path exists but doesnt take this method
+    MethodNotAllowed,
+    /// Client doesnt match the server (protocol version or assets)
+    Conflict,
+    /// Body is longer then the server reads
+    PayloadTooLarge,
+    /// Client sends too often (chat messages)
+    TooManyRequests,
     NotImplemented,
+    /// Server has too many open connections
+    Unavailable,
 }
 impl ResponseStatus {
+    pub fn code(&self) -> u16 {
+        match self {
+            ResponseStatus::Ok => 200,
+            ResponseStatus::ParseError => 400,
+            ResponseStatus::Unauthorized => 401,
+            ResponseStatus::Forbiden => 403,
+            ResponseStatus::None => 404,
+            ResponseStatus::MethodNotAllowed => 405,
+            ResponseStatus::Conflict => 409,
+            ResponseStatus::PayloadTooLarge => 413,
+            ResponseStatus::TooManyRequests => 429,
+            ResponseStatus::Error => 500,
+            ResponseStatus::NotImplemented => 501,
+            ResponseStatus::Unavailable => 503,
+        }
+    }
     pub fn to_string(&self) -> String {
-        format!("HTTP/1.1 {}", match self {
-            ResponseStatus::Ok => "200 OK",
-            ResponseStatus::ParseError => "400 PARSE ERROR",
-            ResponseStatus::Unauthorized => "401 UNAUTHORIZED",
-            ResponseStatus::Forbiden => "403 FORBIDEN ERROR",
-            ResponseStatus::None => "404 NOT FOUND",
-            ResponseStatus::Error => "500 SERVER ERROR",
-            ResponseStatus::NotImplemented => "501 NOT IMPLEMENTED",
+        format!("HTTP/1.1 {0} {1}", self.code(), match self {
+            ResponseStatus::Ok => "OK",
+            ResponseStatus::ParseError => "Bad Request",
+            ResponseStatus::Unauthorized => "Unauthorized",
+            ResponseStatus::Forbiden => "Forbidden",
+            ResponseStatus::None => "Not Found",
+            ResponseStatus::MethodNotAllowed => "Method Not Allowed",
+            ResponseStatus::Conflict => "Conflict",
+            ResponseStatus::PayloadTooLarge => "Content Too Large",
+            ResponseStatus::TooManyRequests => "Too Many Requests",
+            ResponseStatus::Error => "Internal Server Error",
+            ResponseStatus::NotImplemented => "Not Implemented",
+            ResponseStatus::Unavailable => "Service Unavailable",
         })
     }
 }
+/// Machine readable reason of a failed request, send in the body together with a message for
+/// people.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// Body of the request couldnt be read.
+    ParseError,
+    /// Session token is unknown or expired.
+    InvalidSession,
+    WrongPassword,
+    WrongAdminPassword,
+    /// Server was started without an admin password.
+    AdminDisabled,
+    Banned,
+    NameTaken,
+    /// Name is empty, too long or has control characters.
+    InvalidName,
+    RoomFull,
+    TooManyRooms,
+    UnknownRoom,
+    UnknownCharacter,
+    UnknownMap,
+    UnknownPlayer,
+    Muted,
+    EmptyMessage,
+    RateLimited,
+    InvalidTickRate,
+    /// Tick rate cant change while the match is recorded.
+    Recording,
+    NotFound,
+    /// Path exists but doesnt take this method.
+    MethodNotAllowed,
+    Forbidden,
+    /// Request body is too large.
+    TooLarge,
+    /// Server has too many open connections.
+    Busy,
+    ServerError,
+    NotImplemented,
+    /// Code added in a newer version.
+    #[serde(other)]
+    Unknown,
+}
+impl ErrorCode {
+    pub fn status(&self) -> ResponseStatus {
+        match self {
+            ErrorCode::ParseError | ErrorCode::UnknownCharacter | ErrorCode::UnknownMap | ErrorCode::EmptyMessage | ErrorCode::InvalidTickRate |
+                ErrorCode::InvalidName => ResponseStatus::ParseError,
+            ErrorCode::InvalidSession | ErrorCode::WrongPassword | ErrorCode::WrongAdminPassword => ResponseStatus::Unauthorized,
+            ErrorCode::AdminDisabled | ErrorCode::Banned | ErrorCode::NameTaken | ErrorCode::RoomFull | ErrorCode::TooManyRooms |
+                ErrorCode::Muted | ErrorCode::Recording | ErrorCode::Forbidden => ResponseStatus::Forbiden,
+            ErrorCode::UnknownRoom | ErrorCode::UnknownPlayer | ErrorCode::NotFound => ResponseStatus::None,
+            ErrorCode::MethodNotAllowed => ResponseStatus::MethodNotAllowed,
+            ErrorCode::TooLarge => ResponseStatus::PayloadTooLarge,
+            ErrorCode::RateLimited => ResponseStatus::TooManyRequests,
+            ErrorCode::Busy => ResponseStatus::Unavailable,
+            ErrorCode::ServerError | ErrorCode::Unknown => ResponseStatus::Error,
+            ErrorCode::NotImplemented => ResponseStatus::NotImplemented,
+        }
+    }
+    pub fn message(&self) -> &'static str {
+        match self {
+            ErrorCode::ParseError => "request couldnt be read",
+            ErrorCode::InvalidSession => "session expired or doesnt exist",
+            ErrorCode::WrongPassword => "wrong password",
+            ErrorCode::WrongAdminPassword => "wrong admin password",
+            ErrorCode::AdminDisabled => "admin commands are disabled on this server",
+            ErrorCode::Banned => "you are banned from this room",
+            ErrorCode::NameTaken => "name already in use",
+            ErrorCode::InvalidName => "name has to have 1 to 32 characters without control characters",
+            ErrorCode::RoomFull => "room is full",
+            ErrorCode::TooManyRooms => "server cant have more rooms",
+            ErrorCode::UnknownRoom => "room doesnt exist",
+            ErrorCode::UnknownCharacter => "character doesnt exist",
+            ErrorCode::UnknownMap => "map doesnt exist",
+            ErrorCode::UnknownPlayer => "no player with that name",
+            ErrorCode::Muted => "you are muted",
+            ErrorCode::EmptyMessage => "message is empty",
+            ErrorCode::RateLimited => "slow down, too many requests",
+            ErrorCode::InvalidTickRate => "tick rate has to be from 1 to 1000",
+            ErrorCode::Recording => "tick rate cant change while recording",
+            ErrorCode::NotFound => "not found",
+            ErrorCode::MethodNotAllowed => "method not allowed",
+            ErrorCode::Forbidden => "not allowed",
+            ErrorCode::TooLarge => "request body is too large",
+            ErrorCode::Busy => "server is busy, try again later",
+            ErrorCode::ServerError => "server error",
+            ErrorCode::NotImplemented => "not implemented",
+            ErrorCode::Unknown => "unknown error",
+        }
+    }
+    /// Generic code of a status, for errors without a more specific reason.
+    pub fn from_status(status: &ResponseStatus) -> ErrorCode {
+        match status {
+            ResponseStatus::ParseError => ErrorCode::ParseError,
+            ResponseStatus::Unauthorized => ErrorCode::InvalidSession,
+            ResponseStatus::Forbiden | ResponseStatus::Conflict => ErrorCode::Forbidden,
+            ResponseStatus::None => ErrorCode::NotFound,
+            ResponseStatus::MethodNotAllowed => ErrorCode::MethodNotAllowed,
+            ResponseStatus::PayloadTooLarge => ErrorCode::TooLarge,
+            ResponseStatus::TooManyRequests => ErrorCode::RateLimited,
+            ResponseStatus::NotImplemented => ErrorCode::NotImplemented,
+            ResponseStatus::Unavailable => ErrorCode::Busy,
+            ResponseStatus::Ok | ResponseStatus::Error => ErrorCode::ServerError,
+        }
+    }
+}
+/// Body of every failed request, except for the asset mismatch on join.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ErrorResponse {
+    pub code: ErrorCode,
+    pub message: String,
+}
+impl ErrorResponse {
+    pub fn new(code: ErrorCode) -> ErrorResponse {
+        ErrorResponse {
+            code,
+            message: code.message().to_string(),
+        }
+    }
+    pub fn from_string(input: &str) -> Option<Self> {
+        serde_json::from_str::<Self>(input).ok()
+    }
+}
 pub enum BodyType {
     HTML,
     JSON,
     Binary,
+    Text,
 }
 impl BodyType {
     pub fn from_string(body_type: &str) -> Option<BodyType> {
@@ -118,6 +405,7 @@ impl BodyType {
             "text/html" => Some(BodyType::HTML),
             "application/json" => Some(BodyType::JSON),
             "application/binary" => Some(BodyType::Binary),
+            "text/plain" => Some(BodyType::Text),
             _ => None,
         }
     }
@@ -126,6 +414,7 @@ impl BodyType {
             BodyType::HTML => String::from("text/html"),
             BodyType::JSON => String::from("application/json"),
             BodyType::Binary => String::from("application/binary"),
+            BodyType::Text => String::from("text/plain"),
         }
     }
 }
@@ -135,7 +424,11 @@ pub struct Response {
     host_name: String, 
     date: DateTime<Utc>,
     body_type: BodyType,
-    body: String,
+    body: Vec<u8>,
+    /// Other headers like Allow.
+    headers: Vec<(String,String)>,
+    /// Connection is closed after this response.
+    close: bool,
 }
 impl Response {
     pub fn new(status: ResponseStatus, body_type: BodyType, body: &str) -> Response {
@@ -144,44 +437,66 @@ impl Response {
             host_name: String::from("Tree house"),
             date: Utc::now(),
             body_type,
-            body: body.to_string(),
+            body: body.as_bytes().to_vec(),
+            headers: Vec::new(),
+            close: false,
         }
     }
+    /// Response with a body that doesnt have to be a valid text.
+    pub fn binary(status: ResponseStatus, body: Vec<u8>) -> Response {
+        let mut out = Self::new(status,BodyType::Binary,"");
+        out.body = body;
+        out
+    }
+    /// Response without a body, errors get the generic error body of theyr status.
     pub fn status(err: ResponseStatus) -> Response {
-        Self::new(err,BodyType::JSON,"")
+        match err {
+            ResponseStatus::Ok => Self::new(err,BodyType::JSON,""),
+            _ => Self::error(ErrorCode::from_status(&err)),
+        }
+    }
+    /// Failed request with the code and message in the body.
+    pub fn error(code: ErrorCode) -> Response {
+        Self::new(code.status(),BodyType::JSON,&ErrorResponse::new(code).to_string())
+    }
+    pub fn status_code(&self) -> u16 {
+        self.status.code()
     }
-    /// Generates string that can be send along the traffic, few additional information is added
+    pub fn header(&mut self, name: &str, value: &str) {
+        self.headers.push((name.to_string(),value.to_string()));
+    }
+    /// Tells the client that the connection is closed after this response.
+    pub fn close(&mut self) {
+        self.close = true;
+    }
+    pub fn closes(&self) -> bool {
+        self.close
+    }
+    /// Generates bytes that can be send along the traffic, few additional information is added
     /// like current time stamp.
-    pub fn to_string(&self) -> String {
+    pub fn to_bytes(&self) -> Vec<u8> {
         let status = self.status.to_string();
         let server_name = self.host_name.clone();
         let content_leanght = self.body.len();
         let body_type = self.body_type.to_string();
-        let contents = self.body.clone();
-
-        let (_, year) = self.date.year_ce();
-        let curr_time = format!(
-            "{}-{:02}-{:02} {}",
-            year,
-            self.date.month(),
-            self.date.day(),
-            self.date.time(),
-        );
 
+        let curr_time = self.date.format("%a, %d %b %Y %H:%M:%S GMT");
+        let connection = if self.close {"close"} else {"keep-alive"};
 
-        format!(
-"{status}
-Server: {server_name}
-Date: {curr_time}
-Content-Length: {content_leanght}
-Content-Type: {body_type}
-
-{contents}"
-        )
+        let mut out = format!(
+            "{status}\r\nServer: {server_name}\r\nDate: {curr_time}\r\nContent-Length: {content_leanght}\r\nContent-Type: {body_type}\r\nConnection: {connection}\r\n"
+        );
+        for (name,value) in &self.headers {
+            out.push_str(&format!("{name}: {value}\r\n"));
+        }
+        out.push_str("\r\n");
+        let mut out = out.into_bytes();
+        out.extend_from_slice(&self.body);
+        out
     }
 }
 /// Object that can be then taken by character and played out.
-#[derive(Debug,Default,Serialize, Deserialize, Clone)]
+#[derive(Debug,Default,Serialize, Deserialize, Clone, PartialEq)]
 pub struct CharacterInput {
     pub dir: Option<Direction>,
     pub light_attack: bool,
@@ -191,9 +506,6 @@ pub struct CharacterInput {
     pub down: bool,
 }
 impl CharacterInput {
-    pub fn to_string(&self) -> String {
-        serde_json::to_string(self).unwrap()
-    }
     pub fn new() -> CharacterInput {
         CharacterInput {
             dir: Option::None,
@@ -204,6 +516,38 @@ impl CharacterInput {
             down: false,
         }
     } 
+    /// Packs all buttons in to two bytes.
+    pub fn write_bytes(&self, out: &mut ByteWriter) {
+        out.u8(match &self.dir {
+            Option::None => 0,
+            Some(Direction::Left) => 1,
+            Some(Direction::Right) => 2,
+        });
+        out.u8(
+            self.light_attack as u8 |
+            (self.heavy_attack as u8) << 1 |
+            (self.special as u8) << 2 |
+            (self.jump as u8) << 3 |
+            (self.down as u8) << 4
+        );
+    }
+    pub fn read_bytes(input: &mut ByteReader) -> Option<CharacterInput> {
+        let dir = match input.u8()? {
+            0 => Option::None,
+            1 => Some(Direction::Left),
+            2 => Some(Direction::Right),
+            _ => return Option::None,
+        };
+        let buttons = input.u8()?;
+        Some(CharacterInput {
+            dir,
+            light_attack: buttons & 1 != 0,
+            heavy_attack: buttons & 1 << 1 != 0,
+            special: buttons & 1 << 2 != 0,
+            jump: buttons & 1 << 3 != 0,
+            down: buttons & 1 << 4 != 0,
+        })
+    }
     /// Sets everything to neutral position.
     pub fn reset(&mut self) {
         self.light_attack = false;
@@ -212,24 +556,30 @@ impl CharacterInput {
         self.jump = false;
     }
 }
-/// Packed used for comunication between client and server, It has identification for user and
-/// authorazation for server and input for controling characters.
+/// Packed used for comunication between client and server, It has session token of the player
+/// and input for controling characters.
 #[derive(Debug,Default,Serialize, Deserialize, Clone)]
 pub struct GameControlPacket {
-    pub input: CharacterInput,
-    pub server_password: String,
-    pub player: String,
+    /// Inputs of the last few ticks, oldest first. The last one is for the tick in the sequence
+    /// of the packet, the ones before it are repeated in case an earlier packet was lost.
+    pub inputs: Vec<CharacterInput>,
+    pub token: String,
 }
 impl GameControlPacket {
-    pub fn new(server_password: String, player: String,input: CharacterInput) -> GameControlPacket {
+    pub fn new(token: String,inputs: Vec<CharacterInput>) -> GameControlPacket {
         GameControlPacket {
-            input,
-            server_password,
-            player,
+            inputs,
+            token,
         }
     }
-    pub fn to_string(&self) -> String {
-        serde_json::to_string(self).unwrap()
+    /// Every input with the client tick it is for, sequence is the tick of the newest one.
+    pub fn ticks(&self, sequence: u32) -> impl Iterator<Item = (u32,&CharacterInput)> {
+        // Counted back from the newest so the sequence can be anything the client sends, inputs
+        // that would be before tick 1 are left out
+        let newest = self.inputs.len().saturating_sub(1);
+        self.inputs.iter().enumerate()
+            .filter_map(move |(index,input)|Some((sequence.checked_sub(u32::try_from(newest - index).ok()?)?,input)))
+            .filter(|(tick,_)|*tick > 0)
     }
     pub fn from_string(input: String) -> Option<GameControlPacket> {
         if let Ok(output) = serde_json::from_str::<Self>(input.as_str()) {
@@ -238,24 +588,48 @@ impl GameControlPacket {
             None
         }
     }
+    /// Payload of the input packet send over UDP.
+    ///
+    /// token: string, count: u8, input * count
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut out = ByteWriter::new();
+        out.string(&self.token);
+        out.u8(self.inputs.len().min(u8::MAX as usize) as u8);
+        for input in self.inputs.iter().take(u8::MAX as usize) {
+            input.write_bytes(&mut out);
+        }
+        out.into_bytes()
+    }
+    /// Packet without any input is refused.
+    pub fn from_bytes(bytes: &[u8]) -> Option<GameControlPacket> {
+        let mut input = ByteReader::new(bytes);
+        let token = input.string()?;
+        let count = input.u8()?;
+        if count == 0 {
+            return None;
+        }
+        let mut inputs = Vec::new();
+        for _ in 0..count {
+            inputs.push(CharacterInput::read_bytes(&mut input)?);
+        }
+        Some(GameControlPacket {
+            token,
+            inputs,
+        })
+    }
 } 
 #[derive(Debug,Default,Serialize, Deserialize, Clone)]
 pub struct CharacterSwitchRequest {
-    pub server_password: String,
-    pub player_name: String,
+    pub token: String,
     pub character: Option<u32>,
 }
 impl CharacterSwitchRequest {
-    pub fn new(server_password: String, player_name: String, character: Option<u32>) -> CharacterSwitchRequest {
+    pub fn new(token: String, character: Option<u32>) -> CharacterSwitchRequest {
         CharacterSwitchRequest {
-            server_password,
-            player_name,
+            token,
             character,
         }
     }
-    pub fn to_string(&self) -> String {
-        serde_json::to_string(self).unwrap()
-    }
     pub fn from_string(input: String) -> Option<Self> {
         if let Ok(output) = serde_json::from_str::<Self>(input.as_str()) {
             Some(output)
@@ -266,19 +640,27 @@ impl CharacterSwitchRequest {
 }
 #[derive(Debug,Default,Serialize, Deserialize, Clone)]
 pub struct JoinRequest {
+    /// Password of the room, the first room uses server password.
     pub server_password: String,
     pub player_name: String,
+    #[serde(default)]
+    pub room: u32,
+    #[serde(default)]
+    pub protocol: u32,
+    /// Checksums of assets of the client, they have to be the same as on the server.
+    #[serde(default)]
+    pub assets: AssetManifest,
 }
 impl JoinRequest {
-    pub fn new(server_password: String, player_name: String) -> JoinRequest {
+    pub fn new(server_password: String, player_name: String, room: u32, assets: AssetManifest) -> JoinRequest {
         JoinRequest {
             server_password,
             player_name,
+            room,
+            protocol: PROTOCOL_VERSION,
+            assets,
         }
     }
-    pub fn to_string(&self) -> String {
-        serde_json::to_string(self).unwrap()
-    }
     pub fn from_string(input: String) -> Option<Self> {
         if let Ok(output) = serde_json::from_str::<Self>(input.as_str()) {
             Some(output)
@@ -287,3 +669,177 @@ impl JoinRequest {
         }
     }
 }
+/// Joins a room only to watch, spectator has no character and isnt counted as a player.
+#[derive(Debug,Default,Serialize, Deserialize, Clone)]
+pub struct SpectateRequest {
+    pub server_password: String,
+    /// Name admins can kick or ban the spectator by.
+    #[serde(default)]
+    pub name: String,
+    pub room: u32,
+    /// Ticks the match is shown late, so spectators cant help the players.
+    pub delay: usize,
+    #[serde(default)]
+    pub protocol: u32,
+    #[serde(default)]
+    pub assets: AssetManifest,
+}
+impl SpectateRequest {
+    pub fn new(server_password: String, name: String, room: u32, delay: usize, assets: AssetManifest) -> SpectateRequest {
+        SpectateRequest {
+            server_password,
+            name,
+            room,
+            delay,
+            protocol: PROTOCOL_VERSION,
+            assets,
+        }
+    }
+}
+/// Creates a new room, only who knows the server password can create rooms.
+#[derive(Debug,Default,Serialize, Deserialize, Clone)]
+pub struct CreateRoomRequest {
+    pub server_password: String,
+    pub name: String,
+    /// Password needed to join the room, empty for none.
+    pub password: String,
+    pub map_id: usize,
+}
+impl CreateRoomRequest {
+    pub fn new(server_password: String, name: String, password: String, map_id: usize) -> CreateRoomRequest {
+        CreateRoomRequest {
+            server_password,
+            name,
+            password,
+            map_id,
+        }
+    }
+}
+/// What an admin can do with a room or the server.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum AdminCommand {
+    /// Switches the room to another map from the map pool.
+    ChangeMap(usize),
+    /// Removes player or spectator with this name from the room.
+    Kick(String),
+    /// Kicks the player or spectator and doesnt let him (or anyone from his IP) in to the room again.
+    Ban(String),
+    Pause,
+    Resume,
+    SetPassword(String),
+    /// Ticks per second of the whole server, clients have to be started with the same rate.
+    SetRefreshRate(f32),
+    /// Player with this name cant send chat messages, even after joining again.
+    Mute(String),
+    Unmute(String),
+}
+impl AdminCommand {
+    /// Parses command written as text, "map 2", "kick name", "ban name", "pause", "resume",
+    /// "password text", "tps 60", "mute name" or "unmute name".
+    pub fn parse(input: &str) -> Option<AdminCommand> {
+        let (command,argument) = input.trim().split_once(' ').unwrap_or((input.trim(),""));
+        match command {
+            "map" => Some(AdminCommand::ChangeMap(argument.parse().ok()?)),
+            "kick" => Some(AdminCommand::Kick(argument.to_string())),
+            "ban" => Some(AdminCommand::Ban(argument.to_string())),
+            "pause" => Some(AdminCommand::Pause),
+            "resume" => Some(AdminCommand::Resume),
+            "password" => Some(AdminCommand::SetPassword(argument.to_string())),
+            "tps" => Some(AdminCommand::SetRefreshRate(argument.parse().ok()?)),
+            "mute" => Some(AdminCommand::Mute(argument.to_string())),
+            "unmute" => Some(AdminCommand::Unmute(argument.to_string())),
+            _ => None,
+        }
+    }
+}
+/// Admin command, admin password is set on the server start.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AdminRequest {
+    pub admin_password: String,
+    #[serde(default)]
+    pub room: u32,
+    pub command: AdminCommand,
+}
+impl AdminRequest {
+    pub fn new(admin_password: String, room: u32, command: AdminCommand) -> AdminRequest {
+        AdminRequest {
+            admin_password,
+            room,
+            command,
+        }
+    }
+}
+/// Player leaving the game, his character is removed and the name can be used again.
+#[derive(Debug,Default,Serialize, Deserialize, Clone)]
+pub struct LeaveRequest {
+    pub token: String,
+}
+impl LeaveRequest {
+    pub fn new(token: String) -> LeaveRequest {
+        LeaveRequest {
+            token,
+        }
+    }
+}
+/// Player or spectator that lost connection continuing with the same session.
+#[derive(Debug,Default,Serialize, Deserialize, Clone)]
+pub struct ResumeRequest {
+    pub token: String,
+}
+impl ResumeRequest {
+    pub fn new(token: String) -> ResumeRequest {
+        ResumeRequest {
+            token,
+        }
+    }
+}
+/// Chat message from a player, it is send to everyone in his room.
+#[derive(Debug,Default,Serialize, Deserialize, Clone)]
+pub struct ChatRequest {
+    pub token: String,
+    pub text: String,
+}
+impl ChatRequest {
+    pub fn new(token: String, text: String) -> ChatRequest {
+        ChatRequest {
+            token,
+            text,
+        }
+    }
+}
+/// Chat message as it is kept in the history of a room.
+#[derive(Debug,Default,Serialize, Deserialize, Clone)]
+pub struct ChatMessage {
+    pub name: String,
+    pub text: String,
+}
+/// Answer to JoinRequest, token has to be send with every other request of that player.
+#[derive(Debug,Default,Serialize, Deserialize, Clone)]
+pub struct JoinResponse {
+    pub token: String,
+    /// Last messages of the room chat, oldest first.
+    #[serde(default)]
+    pub chat: Vec<ChatMessage>,
+}
+impl JoinResponse {
+    pub fn from_string(input: &str) -> Option<Self> {
+        serde_json::from_str::<Self>(input).ok()
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn input_ticks_dont_overflow() {
+        let newest = CharacterInput {jump: true, ..CharacterInput::new()};
+        let packet = GameControlPacket::new(String::new(),vec![CharacterInput::new(),CharacterInput::new(),newest.clone()]);
+        let ticks: Vec<u32> = packet.ticks(u32::MAX).map(|(tick,_)|tick).collect();
+        assert_eq!(ticks,vec![u32::MAX - 2,u32::MAX - 1,u32::MAX]);
+        let ticks: Vec<u32> = packet.ticks(2).map(|(tick,_)|tick).collect();
+        assert_eq!(ticks,vec![1,2]);
+        let ticks: Vec<(u32,&CharacterInput)> = packet.ticks(1).collect();
+        assert_eq!(ticks,vec![(1,&newest)]);
+        assert_eq!(packet.ticks(0).count(),0);
+    }
+}
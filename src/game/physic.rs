@@ -2,6 +2,7 @@ use serde::{Serialize, Deserialize};
 use crate::{
     client::renderer::*,
     base::sircle::*,
+    base::bytes::*,
 };
 
 /// Basic states that determinated if character can be hit or how it will resolve.
@@ -11,6 +12,23 @@ pub enum ColisionState {
     Invincible,
     UnTouchable,
 }
+impl ColisionState {
+    pub fn to_byte(&self) -> u8 {
+        match self {
+            ColisionState::Vulnerable => 0,
+            ColisionState::Invincible => 1,
+            ColisionState::UnTouchable => 2,
+        }
+    }
+    pub fn from_byte(byte: u8) -> Option<ColisionState> {
+        match byte {
+            0 => Some(ColisionState::Vulnerable),
+            1 => Some(ColisionState::Invincible),
+            2 => Some(ColisionState::UnTouchable),
+            _ => None,
+        }
+    }
+}
 /// What orientation does a ColisionPlane have
 #[derive(Serialize, Deserialize, Clone)]
 pub enum Orientation {
@@ -113,9 +131,36 @@ impl AnimationState {
             AnimationState::AirBornHeavyAttack => {false},
         } 
     }
+    pub fn to_byte(&self) -> u8 {
+        match self {
+            AnimationState::Damadged => 0,
+            AnimationState::Idling => 1,
+            AnimationState::Running => 2,
+            AnimationState::Rizing => 3,
+            AnimationState::Falling => 4,
+            AnimationState::LightAttack => 5,
+            AnimationState::HeavyAttack => 6,
+            AnimationState::AirBornLightAttack => 7,
+            AnimationState::AirBornHeavyAttack => 8,
+        }
+    }
+    pub fn from_byte(byte: u8) -> Option<AnimationState> {
+        match byte {
+            0 => Some(AnimationState::Damadged),
+            1 => Some(AnimationState::Idling),
+            2 => Some(AnimationState::Running),
+            3 => Some(AnimationState::Rizing),
+            4 => Some(AnimationState::Falling),
+            5 => Some(AnimationState::LightAttack),
+            6 => Some(AnimationState::HeavyAttack),
+            7 => Some(AnimationState::AirBornLightAttack),
+            8 => Some(AnimationState::AirBornHeavyAttack),
+            _ => None,
+        }
+    }
 }
 /// What direction is the player looking.
-#[derive(Debug,Serialize, Deserialize, Clone)]
+#[derive(Debug,Serialize, Deserialize, Clone, PartialEq)]
 pub enum Direction {
     Left,
     Right,
@@ -128,6 +173,19 @@ impl Direction {
             Direction::Right => {1.0},
         }
     }
+    pub fn to_byte(&self) -> u8 {
+        match self {
+            Direction::Left => 0,
+            Direction::Right => 1,
+        }
+    }
+    pub fn from_byte(byte: u8) -> Option<Direction> {
+        match byte {
+            0 => Some(Direction::Left),
+            1 => Some(Direction::Right),
+            _ => None,
+        }
+    }
 }
 
 /// State by wich teh player can act
@@ -145,3 +203,25 @@ pub enum State {
     /// user -> Id, frame_hit -> What frame was that attack on
     HitStun(u32,(u32,usize)),
 }
+impl State {
+    pub fn write_bytes(&self, out: &mut ByteWriter) {
+        match self {
+            State::Actionable => out.u8(0),
+            State::Acting => out.u8(1),
+            State::HitStun(frames_left,(user,frame_hit)) => {
+                out.u8(2);
+                out.u32(*frames_left);
+                out.u32(*user);
+                out.u64(*frame_hit as u64);
+            },
+        }
+    }
+    pub fn read_bytes(input: &mut ByteReader) -> Option<State> {
+        match input.u8()? {
+            0 => Some(State::Actionable),
+            1 => Some(State::Acting),
+            2 => Some(State::HitStun(input.u32()?,(input.u32()?,input.u64()? as usize))),
+            _ => None,
+        }
+    }
+}
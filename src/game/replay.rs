@@ -0,0 +1,258 @@
+use serde::{
+    Serialize,
+    Deserialize,
+};
+use std::{
+    collections::{
+        BTreeMap,
+        HashMap,
+    },
+    fs::File,
+    io::{
+        BufRead,
+        BufReader,
+        BufWriter,
+        Write,
+    },
+};
+use crate::game::{
+    character::Character,
+    map::*,
+    networking::CharacterInput,
+};
+
+/// First line of a replay file.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ReplayHeader {
+    pub map_id: usize,
+    /// Instance id and character of everyone at the start of the recording.
+    pub roster: Vec<(u32,u32)>,
+    /// Time step the match was simulated with.
+    pub delta: f32,
+}
+/// One simulated tick, every other line of a replay file.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ReplayFrame {
+    pub tick: usize,
+    /// Whole map before this tick, saved when the map was changed outside of the simulation
+    /// (player joined, switched character) and once in a while to make seeking faster.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keyframe: Option<Map>,
+    pub inputs: BTreeMap<u32,CharacterInput>,
+    /// Checksum of the map after this tick, played tick that ends with a different one desynced.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<u64>,
+}
+/// Writes every tick of a match in to a file as JSON lines.
+pub struct Recorder {
+    file: BufWriter<File>,
+    delta: f32,
+    started: bool,
+    /// Next tick that wasnt recorded yet.
+    next_tick: usize,
+    last_keyframe: usize,
+    /// Map after the last recorded tick, if next tick doesnt start from it a keyframe is needed.
+    last_state: Vec<u8>,
+}
+impl Recorder {
+    /// Ticks between keyframes.
+    const KEYFRAME_INTERVAL: usize = 300;
+    pub fn new(path: &str, delta: f32) -> Option<Recorder> {
+        match File::create(path) {
+            Ok(file) => Some(Recorder {
+                file: BufWriter::new(file),
+                delta,
+                started: false,
+                next_tick: 0,
+                last_keyframe: 0,
+                last_state: Vec::new(),
+            }),
+            Err(error) => {
+                println!("Recording in to \"{path}\" failed: {error:?}");
+                None
+            },
+        }
+    }
+    /// Records one tick, before is the map before simulating it and after is the result.
+    /// Ticks that were already recorded are skipped.
+    pub fn record(&mut self, before: &Map, inputs: &HashMap<u32,CharacterInput>, after: &Map) {
+        let tick = before.counter + 1;
+        if self.started && tick < self.next_tick {
+            return;
+        }
+        if !self.started {
+            let header = ReplayHeader {
+                map_id: before.map_id,
+                roster: before.roster(),
+                delta: self.delta,
+            };
+            let _ = writeln!(self.file,"{}",serde_json::to_string(&header).unwrap());
+        }
+        let before_bytes = before.as_bytes();
+        let keyframe = if !self.started || before_bytes != self.last_state || tick >= self.last_keyframe + Self::KEYFRAME_INTERVAL {
+            self.last_keyframe = tick;
+            Some(before.clone())
+        } else {
+            None
+        };
+        let frame = ReplayFrame {
+            tick,
+            keyframe,
+            inputs: inputs.iter().map(|(id,input)|(*id,input.clone())).collect(),
+            checksum: Some(after.checksum()),
+        };
+        let _ = writeln!(self.file,"{}",serde_json::to_string(&frame).unwrap());
+        let _ = self.file.flush();
+
+        self.started = true;
+        self.next_tick = tick + 1;
+        self.last_state = after.as_bytes();
+    }
+}
+/// Recorded match that can be played, seeked and played at any speed.
+pub struct Replay {
+    pub header: ReplayHeader,
+    frames: Vec<ReplayFrame>,
+    /// Index of the next frame to be played.
+    position: usize,
+    map: Map,
+    /// First tick that didnt end with the recorded checksum.
+    desynced: Option<usize>,
+
+    characters: HashMap<u32,Character>,
+    map_pool: HashMap<usize,MapInformation>,
+}
+impl Replay {
+    /// Reads header and frames of the whole file, error says what is wrong with it.
+    pub fn read(path: &str) -> Result<(ReplayHeader,Vec<ReplayFrame>),String> {
+        let file = File::open(path).map_err(|error|format!("\"{path}\" cant be opened: {error}"))?;
+        let mut lines = BufReader::new(file).lines();
+        let header = lines.next()
+            .and_then(|line|serde_json::from_str::<ReplayHeader>(&line.ok()?).ok())
+            .ok_or_else(||format!("\"{path}\" doesnt start with a replay header"))?;
+        let mut frames = Vec::new();
+        for (number,line) in lines.enumerate() {
+            let frame = line.ok().and_then(|line|serde_json::from_str::<ReplayFrame>(&line).ok())
+                .ok_or_else(||format!("line {0} of \"{path}\" is broken",number + 2))?;
+            frames.push(frame);
+        }
+        Ok((header,frames))
+    }
+    /// Replay of frames that were read, characters and maps are loaded from the assets.
+    pub fn new(header: ReplayHeader, frames: Vec<ReplayFrame>, assets: &String) -> Replay {
+        let map = Map::new(header.map_id);
+        Replay {
+            header,
+            frames,
+            position: 0,
+            map,
+            desynced: None,
+            characters: Character::load_all(None,assets),
+            map_pool: MapInformation::load_all(None,assets),
+        }
+    }
+    /// State of the map after last played tick.
+    pub fn map(&self) -> &Map {
+        &self.map
+    }
+    pub fn first_tick(&self) -> usize {
+        self.frames.first().map_or(0,|frame|frame.tick)
+    }
+    pub fn last_tick(&self) -> usize {
+        self.frames.last().map_or(0,|frame|frame.tick)
+    }
+    /// First tick where the played match differs from the recorded one, recordings from a
+    /// different version or with changed assets desync.
+    pub fn desynced(&self) -> Option<usize> {
+        self.desynced
+    }
+    /// If everything was already played.
+    pub fn finished(&self) -> bool {
+        self.position >= self.frames.len()
+    }
+    /// Plays next tick, returns false at the end of the replay.
+    pub fn step(&mut self) -> bool {
+        let Some(frame) = self.frames.get(self.position) else {
+            return false;
+        };
+        if let Some(keyframe) = &frame.keyframe {
+            self.map = keyframe.clone();
+            // Keyframes dont store last inputs, they are needed for "just pressed" checks
+            if let Some(previous) = self.position.checked_sub(1).and_then(|i|self.frames.get(i)) {
+                for (id,instance) in &mut self.map.characters {
+                    instance.last_input = previous.inputs.get(id).cloned().unwrap_or_default();
+                }
+            }
+        }
+        let inputs: HashMap<u32,CharacterInput> = frame.inputs.iter().map(|(id,input)|(*id,input.clone())).collect();
+        self.map.counter = frame.tick;
+        self.map.set_instance_inputs(&inputs);
+        self.map.update(&self.characters,&self.map_pool,&self.header.delta);
+        if self.desynced.is_none() && frame.checksum.is_some_and(|checksum|checksum != self.map.checksum()) {
+            self.desynced = Some(frame.tick);
+        }
+        self.position += 1;
+        true
+    }
+    /// Jumps to a tick, the nearest earlier keyframe is loaded and played up to that tick.
+    pub fn seek(&mut self, tick: usize) {
+        let target = self.frames.iter().position(|frame|frame.tick > tick).unwrap_or(self.frames.len());
+        let keyframe = self.frames[..target].iter().rposition(|frame|frame.keyframe.is_some()).unwrap_or(0);
+        self.position = keyframe;
+        while self.position < target && self.step() {}
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::physic::Direction;
+
+    /// Records a short match with a player joining in the middle and returns the file.
+    fn record(name: &str) -> String {
+        let assets = String::from("./assets/");
+        let characters = Character::load_all(None,&assets);
+        let map_pool = MapInformation::load_all(None,&assets);
+        let path = std::env::temp_dir().join(name).to_string_lossy().to_string();
+        let delta = 1.0/60.0;
+        let mut recorder = Recorder::new(&path,delta).unwrap();
+        let mut map = Map::new(1);
+        map.new_istance(1);
+        for tick in 0..200 {
+            if tick == 100 {
+                map.new_istance(2);
+            }
+            let before = map.clone();
+            map.counter += 1;
+            let dir = if tick % 40 < 20 {Direction::Left} else {Direction::Right};
+            let inputs: HashMap<u32,CharacterInput> = map.characters.keys()
+                .map(|id|(*id,CharacterInput {dir: Some(dir.clone()), jump: tick % 30 == 0, ..CharacterInput::new()}))
+                .collect();
+            map.set_instance_inputs(&inputs);
+            map.update(&characters,&map_pool,&delta);
+            recorder.record(&before,&inputs,&map);
+        }
+        path
+    }
+    fn load(path: &str) -> Replay {
+        let (header,frames) = Replay::read(path).unwrap();
+        Replay::new(header,frames,&String::from("./assets/"))
+    }
+    #[test]
+    fn recorded_match_plays_without_desync() {
+        let path = record("nebula_replay_test.jsonl");
+        let mut replay = load(&path);
+        while replay.step() {}
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(replay.map().counter,200);
+        assert_eq!(replay.desynced(),None);
+    }
+    #[test]
+    fn wrong_checksum_is_found() {
+        let path = record("nebula_replay_desync_test.jsonl");
+        let mut replay = load(&path);
+        let _ = std::fs::remove_file(&path);
+        replay.frames[150].checksum = Some(0);
+        while replay.step() {}
+        assert_eq!(replay.desynced(),Some(replay.frames[150].tick));
+    }
+}
@@ -0,0 +1,206 @@
+use std::collections::{
+    BTreeMap,
+    HashMap,
+    VecDeque,
+};
+use crate::base::bytes::*;
+use crate::game::{
+    character::Character,
+    map::*,
+    networking::CharacterInput,
+};
+
+/// How rollback mode behaves, same values should be used by server and all clients.
+#[derive(Clone, Copy, Debug)]
+pub struct RollbackSettings {
+    /// How many ticks in the future are local inputs played, hides small latency without rollback.
+    pub input_delay: usize,
+    /// How many past ticks are kept for re-simulating, inputs that are older are dropped.
+    pub max_rollback: usize,
+}
+/// Input of one character for one tick, the only thing that is exchanged in rollback mode.
+#[derive(Clone, Debug)]
+pub struct TickInput {
+    pub instance: u32,
+    pub tick: usize,
+    pub input: CharacterInput,
+}
+impl TickInput {
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut out = ByteWriter::new();
+        out.u32(self.instance);
+        out.u64(self.tick as u64);
+        self.input.write_bytes(&mut out);
+        out.into_bytes()
+    }
+    pub fn from_bytes(bytes: &[u8]) -> Option<TickInput> {
+        let mut input = ByteReader::new(bytes);
+        Some(TickInput {
+            instance: input.u32()?,
+            tick: input.u64()? as usize,
+            input: CharacterInput::read_bytes(&mut input)?,
+        })
+    }
+}
+/// GGPO style simulation. Missing inputs are predicted by repeating the last known one, once the
+/// real input comes and it is different, the map is returned to the saved state before that tick
+/// and simulated again.
+pub struct Rollback {
+    settings: RollbackSettings,
+    map: Map,
+    /// Saved maps before each of the last ticks, oldest first.
+    states: VecDeque<Map>,
+    /// Inputs that came from the players, tick -> instance -> input.
+    confirmed: BTreeMap<usize,HashMap<u32,CharacterInput>>,
+    /// Inputs that were used for simulating each tick, predicted or confirmed.
+    used: BTreeMap<usize,HashMap<u32,CharacterInput>>,
+    /// Earliest tick that has to be simulated again.
+    rollback_to: Option<usize>,
+}
+impl Rollback {
+    pub fn new(map: Map, settings: RollbackSettings) -> Rollback {
+        Rollback {
+            settings,
+            map,
+            states: VecDeque::new(),
+            confirmed: BTreeMap::new(),
+            used: BTreeMap::new(),
+            rollback_to: None,
+        }
+    }
+    /// Current (possibly predicted) state.
+    pub fn map(&self) -> &Map {
+        &self.map
+    }
+    /// Oldest saved state, it can not be changed by any input anymore.
+    pub fn final_state(&self) -> &Map {
+        self.states.front().unwrap_or(&self.map)
+    }
+    /// Oldest tick that can not be changed anymore, map before it, its inputs and map after it.
+    /// None until the whole rollback window is filled.
+    pub fn final_tick(&self) -> Option<(&Map,&HashMap<u32,CharacterInput>,&Map)> {
+        if self.states.len() < self.settings.max_rollback {
+            return None;
+        }
+        let before = self.states.front()?;
+        let inputs = self.used.get(&(before.counter + 1))?;
+        let after = self.states.get(1).unwrap_or(&self.map);
+        Some((before,inputs,after))
+    }
+    /// Last simulated tick.
+    pub fn tick(&self) -> usize {
+        self.map.counter
+    }
+    /// Tick on witch an input made right now will be played.
+    pub fn input_tick(&self) -> usize {
+        self.map.counter + 1 + self.settings.input_delay
+    }
+    /// Stores input for a tick, if the tick was already simulated with a different input it will
+    /// be rolled back on next advance. Inputs older then the rollback window are dropped, so are
+    /// inputs too far in the future, nobody can be that far ahead and they would be kept forever.
+    pub fn add_input(&mut self, input: TickInput) {
+        if input.tick + self.settings.max_rollback <= self.map.counter ||
+            input.tick > self.input_tick() + self.settings.max_rollback {
+                return;
+        }
+        if input.tick <= self.map.counter &&
+            self.used.get(&input.tick).and_then(|used|used.get(&input.instance)) != Some(&input.input) {
+                self.rollback_to = Some(self.rollback_to.map_or(input.tick,|tick|tick.min(input.tick)));
+        }
+        self.confirmed.entry(input.tick).or_default().insert(input.instance,input.input);
+    }
+    /// If this side got too far ahead of the inputs it knows and should wait for others.
+    pub fn waiting(&self) -> bool {
+        let mut newest: HashMap<u32,usize> = HashMap::new();
+        for (tick,inputs) in &self.confirmed {
+            for id in inputs.keys() {
+                newest.insert(*id,*tick);
+            }
+        }
+        self.map.characters.keys()
+            .filter_map(|id|newest.get(id))
+            .any(|tick|tick + self.settings.max_rollback <= self.map.counter)
+    }
+    /// Throws away all saved states, used when the map was changed outside of the simulation.
+    pub fn reset(&mut self, map: Map) {
+        self.map = map;
+        self.states.clear();
+        self.used.clear();
+        self.rollback_to = None;
+    }
+    /// Takes map that is known to be correct and plays everything after it again.
+    pub fn resync(&mut self, mut map: Map) {
+        for (id,instance) in &mut map.characters {
+            instance.last_input = self.input_for(*id,map.counter);
+        }
+        if map.counter == self.map.counter {
+            self.map = map;
+        } else if let Some(position) = self.states.iter().position(|state|state.counter == map.counter) {
+            self.states[position] = map;
+            let tick = self.states[position].counter + 1;
+            self.rollback_to = Some(self.rollback_to.map_or(tick,|old|old.min(tick)));
+        } else {
+            self.reset(map);
+        }
+    }
+    /// Repeats newest known input of the instance, if none is known its neutral.
+    fn input_for(&self, instance: u32, tick: usize) -> CharacterInput {
+        self.confirmed.range(..=tick).rev()
+            .find_map(|(_,inputs)|inputs.get(&instance))
+            .cloned()
+            .unwrap_or_default()
+    }
+    /// Re-simulates ticks with wrong inputs and then simulates one new tick.
+    pub fn advance(&mut self, char_sheet: &HashMap<u32,Character>, map_pool: &HashMap<usize,MapInformation>, delta: &f32) {
+        if let Some(tick) = self.rollback_to.take() &&
+            let Some(position) = self.states.iter().position(|state|state.counter + 1 == tick) {
+                let target = self.map.counter;
+                self.map = self.states[position].clone();
+                self.states.truncate(position);
+                while self.map.counter < target {
+                    self.simulate(char_sheet,map_pool,delta);
+                }
+        }
+        self.simulate(char_sheet,map_pool,delta);
+
+        while self.states.len() > self.settings.max_rollback {
+            self.states.pop_front();
+        }
+        let oldest = self.final_state().counter;
+        // Inputs before the window are merged so the newest one of each instance can be repeated.
+        let newer = self.confirmed.split_off(&oldest);
+        let older = std::mem::replace(&mut self.confirmed,newer);
+        if oldest > 0 {
+            for (_,inputs) in older {
+                self.confirmed.entry(oldest - 1).or_default().extend(inputs);
+            }
+        }
+        self.used = self.used.split_off(&oldest);
+    }
+    fn simulate(&mut self, char_sheet: &HashMap<u32,Character>, map_pool: &HashMap<usize,MapInformation>, delta: &f32) {
+        self.states.push_back(self.map.clone());
+        let tick = self.map.counter + 1;
+        let inputs: HashMap<u32,CharacterInput> = self.map.characters.keys()
+            .map(|id|(*id,self.input_for(*id,tick)))
+            .collect();
+        self.map.counter = tick;
+        self.map.set_instance_inputs(&inputs);
+        self.map.update(char_sheet,map_pool,delta);
+        self.used.insert(tick,inputs);
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inputs_outside_the_window_are_dropped() {
+        let settings = RollbackSettings {input_delay: 2, max_rollback: 8};
+        let mut rollback = Rollback::new(Map::new(1),settings);
+        let newest = rollback.input_tick() + settings.max_rollback;
+        for tick in [newest,newest + 1,usize::from(u16::MAX)] {
+            rollback.add_input(TickInput {instance: 0, tick, input: CharacterInput::new()});
+        }
+        assert_eq!(rollback.confirmed.keys().copied().collect::<Vec<usize>>(),vec![newest]);
+    }
+}
@@ -0,0 +1,586 @@
+use serde::{
+    Serialize,
+    Deserialize,
+};
+use std::{
+    collections::{
+        HashMap,
+        VecDeque,
+    },
+    net::{
+        IpAddr,
+        SocketAddr,
+    },
+};
+use crate::game::{
+    Player,
+    session::Session,
+    character::{
+        Character,
+        CharacterInstance,
+    },
+    map::*,
+    networking::*,
+    replay::Recorder,
+    rollback::*,
+};
+
+/// Public information about a room, shown in the room list.
+#[derive(Debug,Default,Serialize, Deserialize, Clone)]
+pub struct RoomInfo {
+    pub id: u32,
+    pub name: String,
+    pub map_id: usize,
+    pub players: usize,
+    pub max_players: usize,
+    pub spectators: usize,
+    pub password: bool,
+}
+impl RoomInfo {
+    pub fn list_to_string(list: &Vec<RoomInfo>) -> String {
+        serde_json::to_string(list).unwrap()
+    }
+    pub fn list_from_string(input: &str) -> Option<Vec<RoomInfo>> {
+        serde_json::from_str::<Vec<RoomInfo>>(input).ok()
+    }
+}
+/// Someone who only watches the match.
+#[derive(Clone)]
+pub struct Spectator {
+    session: Session,
+    /// Only used to kick or ban him, two spectators can have the same name.
+    name: String,
+    /// Ticks since the last packet from this spectator.
+    last_ping: usize,
+    /// Known after first watch packet.
+    addres: Option<SocketAddr>,
+    delay: usize,
+}
+/// One match running on the server, every room has its own map and players.
+pub struct Room {
+    pub name: String,
+    password: String,
+    /// Players of this room by theyr session token.
+    pub players: HashMap<String,Player>,
+    /// Spectators of this room by theyr session token.
+    pub spectators: HashMap<String,Spectator>,
+    /// Players that stopped sending packets with theyr character taken out of the map, they can
+    /// resume until theyr session expires.
+    pub disconnected: HashMap<String,(Player,Option<CharacterInstance>)>,
+    /// Last snapshots with theyr tick, spectators are send the ones that are old enough.
+    history: VecDeque<(u32,Vec<u8>)>,
+    pub map: Map,
+    /// If set, the map is simulated in rollback mode and inputs are relayed to other players.
+    rollback: Option<Rollback>,
+    recorder: Option<Recorder>,
+    /// Id of the newest event that was send in a rollback snapshot.
+    sent_event: Option<u32>,
+    /// Ticks without any player in the room.
+    pub empty_ticks: usize,
+    /// Paused room doesnt simulate, snapshots are still send.
+    paused: bool,
+    /// When did the last tick start and how long is one tick, clients synchronise with it.
+    last_tick: std::time::Instant,
+    delta: f32,
+    /// Names and IPs of banned players, they cant join again.
+    banned_names: Vec<String>,
+    banned_ips: Vec<IpAddr>,
+    /// Last chat messages, new players get them when they join.
+    chat: VecDeque<ChatMessage>,
+    /// Names of players that cant send chat messages.
+    muted: Vec<String>,
+}
+impl Room {
+    /// In rollback mode full snapshots are send only once per this many ticks.
+    const ROLLBACK_SNAPSHOT_INTERVAL: usize = 30;
+    /// Most players in one room, spectators are not counted.
+    pub const MAX_PLAYERS: usize = 16;
+    /// Longest name of a player or spectator, names are in every snapshot with an event.
+    pub const MAX_NAME_LENGTH: usize = 32;
+    /// Longest delay a spectator can ask for.
+    pub const MAX_SPECTATOR_DELAY: usize = 600;
+    /// How many chat messages are kept in the history.
+    const MAX_CHAT_HISTORY: usize = 32;
+    /// Longer messages are cut.
+    const MAX_CHAT_LENGTH: usize = 120;
+    /// One player can send at most this many messages in CHAT_RATE_WINDOW.
+    const CHAT_RATE_LIMIT: usize = 5;
+    const CHAT_RATE_WINDOW: std::time::Duration = std::time::Duration::from_secs(10);
+    pub fn new(name: String, password: String, map_id: usize, rollback: Option<RollbackSettings>) -> Room {
+        let map = Map::new(map_id);
+        Room {
+            name,
+            password,
+            players: HashMap::new(),
+            spectators: HashMap::new(),
+            disconnected: HashMap::new(),
+            history: VecDeque::new(),
+            rollback: rollback.map(|settings|Rollback::new(map.clone(),settings)),
+            map,
+            recorder: None,
+            sent_event: None,
+            empty_ticks: 0,
+            paused: false,
+            last_tick: std::time::Instant::now(),
+            delta: 0.0,
+            banned_names: Vec::new(),
+            banned_ips: Vec::new(),
+            chat: VecDeque::new(),
+            muted: Vec::new(),
+        }
+    }
+    pub fn info(&self, id: u32) -> RoomInfo {
+        RoomInfo {
+            id,
+            name: self.name.clone(),
+            map_id: self.map.map_id,
+            players: self.players.len(),
+            max_players: Self::MAX_PLAYERS,
+            spectators: self.spectators.len(),
+            password: !self.password.is_empty(),
+        }
+    }
+    /// Records every tick of this room.
+    pub fn record(&mut self, recorder: Recorder) {
+        self.recorder = Some(recorder);
+    }
+    pub fn check_password(&self, password: &str) -> bool {
+        self.password == password
+    }
+    pub fn set_password(&mut self, password: String) {
+        self.password = password;
+    }
+    pub fn set_paused(&mut self, paused: bool) {
+        if self.paused != paused {
+            self.map.push_event(if paused {ServerEvent::Paused} else {ServerEvent::Resumed});
+        }
+        self.paused = paused;
+    }
+    pub fn is_banned(&self, name: &String, ip: Option<IpAddr>) -> bool {
+        self.banned_names.contains(name) || ip.is_some_and(|ip|self.banned_ips.contains(&ip))
+    }
+    /// Removes player (active or disconnected) and spectators by theyr name, banned ones cant
+    /// join or watch again, not even from the same IP. Returns false if there is no one with
+    /// that name.
+    pub fn kick(&mut self, name: &String, ban: bool) -> bool {
+        if ban {
+            self.banned_names.push(name.clone());
+        }
+        let mut addreses = Vec::new();
+        let watching: Vec<String> = self.spectators.iter()
+            .filter(|(_,s)|s.name == *name)
+            .map(|(token,_)|token.clone())
+            .collect();
+        for token in &watching {
+            if let Some(mut spectator) = self.spectators.remove(token) {
+                spectator.session.revoke();
+                addreses.extend(spectator.addres);
+            }
+        }
+        let mut found = !watching.is_empty();
+        if let Some(token) = self.disconnected.iter().find(|(_,(p,_))|p.name == *name).map(|(token,_)|token.clone()) &&
+            let Some((mut player,_)) = self.disconnected.remove(&token) {
+                player.session.revoke();
+                addreses.extend(player.addres);
+                self.map.push_event(ServerEvent::Kicked(player.name));
+                found = true;
+        }
+        if let Some(token) = self.players.iter().find(|(_,p)|p.name == *name).map(|(token,_)|token.clone()) &&
+            let Some(mut player) = self.players.remove(&token) {
+                player.session.revoke();
+                addreses.extend(player.addres);
+                self.map.remove_instance(player.instance);
+                self.map.push_event(ServerEvent::Kicked(player.name));
+                found = true;
+        }
+        if ban {
+            self.banned_ips.extend(addreses.iter().map(|addres|addres.ip()));
+        }
+        found
+    }
+    /// Muted player can still play, his chat messages are refused.
+    pub fn mute(&mut self, name: &String, mute: bool) {
+        self.muted.retain(|muted|muted != name);
+        if mute {
+            self.muted.push(name.clone());
+        }
+    }
+    /// Sends chat message to everyone in the room with the next snapshots. Control characters
+    /// are removed and long messages are cut.
+    pub fn chat(&mut self, token: &String, text: &str) -> Result<(),ErrorCode> {
+        let muted = &self.muted;
+        let Some(player) = self.players.get_mut(token).filter(|player|player.session.is_valid()) else {
+            return Err(ErrorCode::InvalidSession);
+        };
+        if muted.contains(&player.name) {
+            return Err(ErrorCode::Muted);
+        }
+        let text: String = text.chars()
+            .filter(|character|!character.is_control())
+            .take(Self::MAX_CHAT_LENGTH)
+            .collect();
+        let text = text.trim().to_string();
+        if text.is_empty() {
+            return Err(ErrorCode::EmptyMessage);
+        }
+        let now = std::time::Instant::now();
+        while player.chat_sent.front().is_some_and(|sent|now.duration_since(*sent) > Self::CHAT_RATE_WINDOW) {
+            player.chat_sent.pop_front();
+        }
+        if player.chat_sent.len() >= Self::CHAT_RATE_LIMIT {
+            return Err(ErrorCode::RateLimited);
+        }
+        player.chat_sent.push_back(now);
+        player.session.refresh();
+        player.last_ping = 0;
+
+        let name = player.name.clone();
+        self.map.push_event(ServerEvent::Chat(name.clone(),text.clone()));
+        self.chat.push_back(ChatMessage {name,text});
+        if self.chat.len() > Self::MAX_CHAT_HISTORY {
+            self.chat.pop_front();
+        }
+        Ok(())
+    }
+    /// Chat history, oldest message first.
+    pub fn chat_history(&self) -> Vec<ChatMessage> {
+        self.chat.iter().cloned().collect()
+    }
+    /// Current tick, part of the next tick that already passed and ticks per second.
+    pub fn time(&self) -> (u32,f32,f32) {
+        if self.delta <= 0.0 {
+            return (self.map.counter as u32,0.0,0.0);
+        }
+        let progress = (self.last_tick.elapsed().as_secs_f32()/self.delta).min(1.0);
+        (self.map.counter as u32,progress,1.0/self.delta)
+    }
+    pub fn is_rollback(&self) -> bool {
+        self.rollback.is_some()
+    }
+    /// Name has to be short and printable, it is send with events to everyone in the room.
+    fn check_name(name: &str) -> Result<(),ErrorCode> {
+        if name.trim().is_empty() || name.chars().count() > Self::MAX_NAME_LENGTH || name.chars().any(char::is_control) {
+            return Err(ErrorCode::InvalidName);
+        }
+        Ok(())
+    }
+    /// If no player of that name is in the room and the room isnt full, create that player and
+    /// return his session token. Disconnected players keep theyr names and places.
+    pub fn join(&mut self, name: String, session_timeout: std::time::Duration) -> Result<String,ErrorCode> {
+        Self::check_name(&name)?;
+        if self.players.values().chain(self.disconnected.values().map(|(player,_)|player)).any(|player|player.name == name) {
+            return Err(ErrorCode::NameTaken);
+        }
+        if self.players.len() + self.disconnected.len() >= Self::MAX_PLAYERS {
+            return Err(ErrorCode::RoomFull);
+        }
+        self.map.push_event(ServerEvent::Joined(name.clone()));
+        let player = Player::new(name,session_timeout);
+        let token = player.session.token().to_string();
+        self.players.insert(token.clone(),player);
+        Ok(token)
+    }
+    /// Adds spectator and returns his session token.
+    pub fn spectate(&mut self, name: String, delay: usize, session_timeout: std::time::Duration) -> Result<String,ErrorCode> {
+        Self::check_name(&name)?;
+        let spectator = Spectator {
+            session: Session::new(session_timeout),
+            name,
+            last_ping: 0,
+            addres: None,
+            delay: delay.min(Self::MAX_SPECTATOR_DELAY),
+        };
+        let token = spectator.session.token().to_string();
+        self.spectators.insert(token.clone(),spectator);
+        Ok(token)
+    }
+    /// Spectator told where to send snapshots, returns false if the token isnt valid.
+    pub fn watch(&mut self, token: &String, addres: SocketAddr) -> bool {
+        if let Some(spectator) = self.spectators.get_mut(token) && spectator.session.is_valid() {
+            spectator.session.refresh();
+            spectator.addres = Some(addres);
+            spectator.last_ping = 0;
+            return true;
+        }
+        false
+    }
+    /// If the token belongs to anyone in this room, player or spectator.
+    pub fn has_token(&self, token: &String) -> bool {
+        self.players.contains_key(token) || self.spectators.contains_key(token) || self.disconnected.contains_key(token)
+    }
+    /// Disconnected player gets his character back, player or spectator that didnt time out yet
+    /// only refreshes his session. Returns false if the session is unknown or expired, expired
+    /// player is removed right away so his name can be used again.
+    pub fn resume(&mut self, token: &String) -> bool {
+        if let Some((mut player,instance)) = self.disconnected.remove(token) {
+            if !player.session.is_valid() {
+                self.map.push_event(ServerEvent::PlayerLeft(player.name));
+                return false;
+            }
+            if let Some(instance) = instance && let Some(id) = player.instance {
+                self.map.characters.insert(id,instance);
+            }
+            player.session.refresh();
+            player.last_ping = 0;
+            // Address and inputs come again with the next packets
+            player.addres = None;
+            player.inputs.clear();
+            self.map.push_event(ServerEvent::Reconnected(player.name.clone()));
+            self.players.insert(token.clone(),player);
+            return true;
+        }
+        if let Some(spectator) = self.spectators.get_mut(token) && spectator.session.is_valid() {
+            spectator.session.refresh();
+            spectator.last_ping = 0;
+            return true;
+        }
+        if self.authenticate(token).is_some() {
+            return true;
+        }
+        if self.players.contains_key(token) {
+            self.leave(token);
+        }
+        false
+    }
+    /// Finds player by his session token, expired sessions are ignored.
+    /// Every succesfull authentication refreshes the session.
+    pub fn authenticate(&mut self, token: &String) -> Option<&mut Player> {
+        let player = self.players.get_mut(token)?;
+        if !player.session.is_valid() {
+            return None;
+        }
+        player.session.refresh();
+        Some(player)
+    }
+    /// Removes the player and his character, or the spectator. Theyr session is revoked.
+    pub fn leave(&mut self, token: &String) -> Option<Player> {
+        if let Some(mut spectator) = self.spectators.remove(token) {
+            spectator.session.revoke();
+            return None;
+        }
+        if let Some((mut player,_)) = self.disconnected.remove(token) {
+            player.session.revoke();
+            self.map.push_event(ServerEvent::PlayerLeft(player.name.clone()));
+            return Some(player);
+        }
+        let mut player = self.players.remove(token)?;
+        player.session.revoke();
+        self.map.remove_player(&player.name,player.instance);
+        Some(player)
+    }
+    /// Buffers inputs from the packet that the player didnt send before, they are applied on the
+    /// next ticks. Returns instance of the player if the packet is newer then the last one.
+    pub fn update_player(&mut self, input: &GameControlPacket, sequence: u32, addres: SocketAddr) -> Option<Option<u32>> {
+        let player = self.authenticate(&input.token)?;
+        player.buffer_inputs(input,sequence);
+        player.addres = Some(addres);
+        player.last_ping = 0;
+        if sequence <= player.sequence {
+            return None;
+        }
+        player.sequence = sequence;
+        Some(player.instance)
+    }
+    /// Passes input to the rollback simulation.
+    pub fn add_input(&mut self, input: TickInput) {
+        if let Some(rollback) = &mut self.rollback {
+            rollback.add_input(input);
+        }
+    }
+    /// Addreses of all players that already send some input.
+    pub fn addreses(&self) -> Vec<SocketAddr> {
+        self.players.values().filter_map(|p|p.addres).collect()
+    }
+    /// Where and with what ack are snapshots send, with instance of each player. Ack is the last
+    /// input applied to the map.
+    pub fn targets(&self) -> Vec<(SocketAddr,u32,Option<u32>)> {
+        self.players.values()
+            .filter_map(|p| Some((p.addres?,p.applied,p.instance)))
+            .collect()
+    }
+    /// Where to send snapshot to each spectator, with the newest snapshot that is old enough for him.
+    pub fn spectator_targets(&self) -> Vec<(SocketAddr,u32,Vec<u8>)> {
+        let tick = self.map.counter;
+        self.spectators.values()
+            .filter_map(|s|{
+                let (sequence,snapshot) = self.history.iter().rev().find(|(sequence,_)|*sequence as usize + s.delay <= tick)?;
+                Some((s.addres?,*sequence,snapshot.clone()))
+            })
+            .collect()
+    }
+    /// Logic for switching characters between states.
+    pub fn switch_character(&mut self, token: &String, character: Option<u32>) -> Result<(),ErrorCode> {
+        let Some(player) = self.authenticate(token) else {
+            return Err(ErrorCode::InvalidSession);
+        };
+        player.last_ping = 0;
+        let instance_id_op = player.instance;
+        let current_character_id = instance_id_op
+            .and_then(|id|self.map.characters.get(&id))
+            .map(|instance|instance.character);
+
+        match (current_character_id,character) {
+            (Option::None,Option::Some(new_id)) => {
+                let new_object_id = self.map.new_istance(new_id);
+                if let Some(player) = self.players.get_mut(token) {
+                    player.instance = Some(new_object_id);
+                }
+            },
+            (Option::Some(_),Option::None) => {
+                if let Some(id) = instance_id_op {
+                    self.map.characters.remove(&id);
+                }
+                if let Some(player) = self.players.get_mut(token) {
+                    player.instance = Option::None;
+                }
+            },
+            (Option::Some(_),Option::Some(new_id)) =>
+                if let Some(instance_id) = instance_id_op && let Some(char_instance) = self.map.characters.get_mut(&instance_id) {
+                    char_instance.character = new_id;
+                    char_instance.reset();
+                }else{return Err(ErrorCode::ServerError);},
+            (Option::None,Option::None) => {},
+        }
+        Ok(())
+    }
+    /// Players that didnt send anything for too long are disconnected, theyr character is taken
+    /// out of the map until they resume. Players whose session expired are removed and theyr
+    /// names can be used again.
+    fn remove_idle(&mut self, idle_ticks: usize) {
+        let idle: Vec<String> = self.players.iter()
+            .filter(|(_,p)|p.last_ping > idle_ticks || !p.session.is_valid())
+            .map(|(token,_)|token.clone())
+            .collect();
+        for token in idle {
+            let Some(player) = self.players.remove(&token) else {
+                continue;
+            };
+            if player.session.is_valid() {
+                let instance = player.instance.and_then(|id|self.map.characters.remove(&id));
+                self.map.push_event(ServerEvent::Disconnected(player.name.clone()));
+                self.disconnected.insert(token,(player,instance));
+            } else {
+                self.map.remove_player(&player.name,player.instance);
+            }
+        }
+        let expired: Vec<String> = self.disconnected.iter()
+            .filter(|(_,(p,_))|!p.session.is_valid())
+            .map(|(token,_)|token.clone())
+            .collect();
+        for token in expired {
+            self.leave(&token);
+        }
+        self.spectators.retain(|_,s|s.last_ping <= idle_ticks && s.session.is_valid());
+    }
+    /// Saves confirmed state for spectators, only as much as the longest delay needs.
+    fn save_history(history: &mut VecDeque<(u32,Vec<u8>)>, spectators: &HashMap<String,Spectator>, snapshot: impl FnOnce() -> Vec<u8>, tick: u32) {
+        let Some(longest) = spectators.values().map(|s|s.delay).max() else {
+            history.clear();
+            return;
+        };
+        if history.back().is_none_or(|(last,_)|*last != tick) {
+            history.push_back((tick,snapshot()));
+        }
+        // The newest snapshot old enough for the longest delay has to stay
+        while history.len() > 1 && history.get(1).is_some_and(|(second,_)|*second as usize + longest <= tick as usize) {
+            history.pop_front();
+        }
+    }
+    /// Simulates one tick, returns tick and the snapshot if it should be send to the players.
+    pub fn tick(&mut self, char_sheet: &HashMap<u32,Character>, map_pool: &HashMap<usize,MapInformation>, delta: &f32, idle_ticks: usize) -> Option<(u32,Vec<u8>)> {
+        self.last_tick = std::time::Instant::now();
+        self.delta = *delta;
+        self.remove_idle(idle_ticks);
+        if self.players.is_empty() && self.spectators.is_empty() && self.disconnected.is_empty() {
+            self.empty_ticks += 1;
+        } else {
+            self.empty_ticks = 0;
+        }
+        // Paused room keeps the inputs for later
+        if !self.paused {
+            self.players.values_mut().for_each(|p|p.next_input());
+        }
+        let players_input = self.players.clone();
+        self.players.values_mut().for_each(|p|p.last_ping += 1);
+        self.spectators.values_mut().for_each(|s|s.last_ping += 1);
+        if self.paused {
+            return Some((self.map.counter as u32,self.map.as_bytes()));
+        }
+
+        let map = &mut self.map;
+        if let Some(rollback) = &mut self.rollback {
+            // Events are not simulated, they stay outside the saved states so a chat message
+            // doesnt throw the rollback history away
+            let events = std::mem::take(&mut map.events);
+            // Players joined or switched characters, saved states are not valid anymore
+            let changed = map.as_bytes() != rollback.map().as_bytes();
+            if changed {
+                rollback.reset(map.clone());
+            }
+            rollback.advance(char_sheet,map_pool,delta);
+            *map = rollback.map().clone();
+            map.events = events;
+            // Spectators see only the state that can not change anymore
+            let mut confirmed = rollback.final_state().clone();
+            confirmed.events = map.events.clone();
+            Self::save_history(&mut self.history,&self.spectators,||confirmed.as_bytes(),confirmed.counter as u32);
+            if let Some(recorder) = &mut self.recorder && let Some((before,inputs,after)) = rollback.final_tick() {
+                recorder.record(before,inputs,after);
+            }
+            // New events are send right away
+            let newest = map.events.last().map(|(id,_)|*id);
+            if changed || newest != self.sent_event || map.counter.is_multiple_of(Self::ROLLBACK_SNAPSHOT_INTERVAL) {
+                self.sent_event = newest;
+                return Some((map.counter as u32,confirmed.as_bytes()));
+            }
+            return None;
+        }
+        let before = self.recorder.as_ref().map(|_|map.clone());
+        map.counter += 1;
+        map.set_inputs(players_input);
+        let inputs = map.inputs();
+        map.update(char_sheet,map_pool,delta);
+        if let Some(recorder) = &mut self.recorder && let Some(before) = before {
+            recorder.record(&before,&inputs,map);
+        }
+        let snapshot = map.as_bytes();
+        Self::save_history(&mut self.history,&self.spectators,||snapshot.clone(),map.counter as u32);
+        Some((map.counter as u32,snapshot))
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bad_names_are_refused() {
+        let mut room = Room::new(String::from("Test"),String::new(),1,None);
+        let timeout = std::time::Duration::from_secs(60);
+        for name in ["","   ","bell\u{7}","line\nbreak",&"x".repeat(Room::MAX_NAME_LENGTH + 1)] {
+            assert!(matches!(room.join(name.to_string(),timeout),Err(ErrorCode::InvalidName)),"{name:?}");
+            assert!(matches!(room.spectate(name.to_string(),0,timeout),Err(ErrorCode::InvalidName)),"{name:?}");
+        }
+        assert!(room.join("ž".repeat(Room::MAX_NAME_LENGTH),timeout).is_ok());
+        assert!(room.spectate(String::from("watcher"),0,timeout).is_ok());
+        assert!(room.map.events.iter().all(|(_,event)|matches!(event,ServerEvent::Joined(name) if name.chars().count() == Room::MAX_NAME_LENGTH)));
+    }
+    #[test]
+    fn chat_keeps_rollback_history() {
+        let assets = String::from("./assets/");
+        let characters = Character::load_all(None,&assets);
+        let map_pool = MapInformation::load_all(None,&assets);
+        let settings = RollbackSettings {input_delay: 2, max_rollback: 8};
+        let mut room = Room::new(String::from("Test"),String::new(),1,Some(settings));
+        let token = room.join(String::from("player"),std::time::Duration::from_secs(60)).unwrap();
+        room.switch_character(&token,Some(1)).unwrap();
+        for _ in 0..20 {
+            room.tick(&characters,&map_pool,&(1.0/60.0),usize::MAX);
+        }
+        room.chat(&token,"hello").unwrap();
+        let (_,snapshot) = room.tick(&characters,&map_pool,&(1.0/60.0),usize::MAX).unwrap();
+        let rollback = room.rollback.as_ref().unwrap();
+        assert_eq!(rollback.final_state().counter + settings.max_rollback,rollback.tick());
+        let snapshot = Map::from_bytes(&snapshot).unwrap();
+        assert!(snapshot.events.iter().any(|(_,event)|matches!(event,ServerEvent::Chat(_,text) if text == "hello")));
+    }
+}
@@ -0,0 +1,36 @@
+use std::time::{
+    Duration,
+    Instant,
+};
+
+/// Random token given to a player on join, every other request is authenticated by it.
+/// Token expires when it isnt used for a while and can be revoked at any time.
+#[derive(Clone)]
+pub struct Session {
+    token: String,
+    expires: Instant,
+    timeout: Duration,
+}
+impl Session {
+    pub fn new(timeout: Duration) -> Session {
+        Session {
+            token: uuid::Uuid::new_v4().to_string(),
+            expires: Instant::now() + timeout,
+            timeout,
+        }
+    }
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+    pub fn is_valid(&self) -> bool {
+        Instant::now() < self.expires
+    }
+    /// Moves expiration, called on every authenticated request.
+    pub fn refresh(&mut self) {
+        self.expires = Instant::now() + self.timeout;
+    }
+    /// Makes the token invalid right away.
+    pub fn revoke(&mut self) {
+        self.expires = Instant::now();
+    }
+}
@@ -0,0 +1,116 @@
+use serde::{
+    Serialize,
+    Deserialize,
+};
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    fs,
+    net::{
+        SocketAddr,
+        UdpSocket,
+    },
+    sync::mpsc,
+    thread,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+use crate::base::random::Random;
+
+/// Bad network conditions applied to every UDP packet a client or server sends, for testing the
+/// netcode locally. Latency is one way, client and server in one process both apply it.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct NetworkConditions {
+    /// Milliseconds every packet is held back.
+    #[serde(default)]
+    pub latency: u64,
+    /// Up to this many milliseconds are randomly added to the latency.
+    #[serde(default)]
+    pub jitter: u64,
+    /// Chance (0 to 1) that a packet is dropped.
+    #[serde(default)]
+    pub loss: f32,
+    /// Chance (0 to 1) that a packet is send twice.
+    #[serde(default)]
+    pub duplicate: f32,
+    /// Chance (0 to 1) that a packet is held back long enough to come after the next ones.
+    #[serde(default)]
+    pub reorder: f32,
+}
+impl NetworkConditions {
+    /// Loads conditions from a JSON file, missing values are 0.
+    pub fn load(path: &str) -> Option<NetworkConditions> {
+        serde_json::from_str(&fs::read_to_string(path).ok()?).ok()
+    }
+    /// If nothing would be changed and packets can be send directly.
+    pub fn is_ideal(&self) -> bool {
+        *self == Self::default()
+    }
+}
+/// Packet waiting in the simulator, (when to send it, order it came in, bytes, where), the
+/// earliest is on top of the heap.
+type Queued = Reverse<(Instant,u64,Vec<u8>,SocketAddr)>;
+/// Sends packets from a background thread that delays, drops, duplicates and reorders them.
+#[derive(Clone)]
+pub struct NetworkSimulator {
+    sender: mpsc::Sender<(Vec<u8>,SocketAddr)>,
+}
+impl NetworkSimulator {
+    /// Extra delay of a reordered packet.
+    const REORDER_DELAY: Duration = Duration::from_millis(30);
+    /// Starts the sending thread, None if the conditions are ideal or the socket cant be cloned.
+    pub fn new(conditions: NetworkConditions, socket: &UdpSocket) -> Option<NetworkSimulator> {
+        if conditions.is_ideal() {
+            return None;
+        }
+        let socket = socket.try_clone().ok()?;
+        let (sender,receiver) = mpsc::channel::<(Vec<u8>,SocketAddr)>();
+        thread::spawn(move || {
+            let mut random = Random::new();
+            let mut queue: BinaryHeap<Queued> = BinaryHeap::new();
+            // Keeps packets with the same time in the order they came
+            let mut order: u64 = 0;
+            loop {
+                let received = match queue.peek() {
+                    Some(Reverse((due,_,_,_))) => receiver.recv_timeout(due.saturating_duration_since(Instant::now())),
+                    None => receiver.recv().map_err(|_|mpsc::RecvTimeoutError::Disconnected),
+                };
+                match received {
+                    Ok((bytes,addres)) => {
+                        if random.chance(conditions.loss) {
+                            continue;
+                        }
+                        let copies = if random.chance(conditions.duplicate) {2} else {1};
+                        for _ in 0..copies {
+                            let mut delay = Duration::from_millis(conditions.latency + random.below(conditions.jitter + 1));
+                            if random.chance(conditions.reorder) {
+                                delay += Self::REORDER_DELAY;
+                            }
+                            order += 1;
+                            queue.push(Reverse((Instant::now() + delay,order,bytes.clone(),addres)));
+                        }
+                    },
+                    Err(mpsc::RecvTimeoutError::Timeout) => {},
+                    // Nothing is send anymore, packets still waiting are dropped
+                    Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                }
+                while let Some(Reverse((due,_,_,_))) = queue.peek() && *due <= Instant::now() {
+                    if let Some(Reverse((_,_,bytes,addres))) = queue.pop() {
+                        let _ = socket.send_to(&bytes,addres);
+                    }
+                }
+            }
+        });
+        Some(NetworkSimulator {sender})
+    }
+    /// Queues packet for sending, returns its size.
+    pub fn send(&self, bytes: Vec<u8>, addres: SocketAddr) -> usize {
+        let size = bytes.len();
+        match self.sender.send((bytes,addres)) {
+            Ok(()) => size,
+            Err(_) => 0,
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use std::net::{
+    SocketAddr,
+    ToSocketAddrs,
+    UdpSocket,
+};
+use std::io::ErrorKind;
+use crate::base::bytes::*;
+use crate::game::map::Map;
+use crate::game::simulator::NetworkSimulator;
+
+/// What is carried inside of a packet.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PacketKind {
+    /// Client -> Server, GameControlPacket.
+    Input,
+    /// Server -> Client, binary map snapshot.
+    Snapshot,
+    /// Server -> Client, TickInput of other player in rollback mode.
+    Inputs,
+    /// Spectator -> Server, session token, tells the server where to send snapshots.
+    Watch,
+    /// Client -> Any server on the network, empty, asks servers to introduce themselves.
+    Probe,
+    /// Server -> Client, ServerInfo, answer to a probe.
+    Info,
+    /// Client -> Server, TimeRequest, asks for the tick of his room.
+    Ping,
+    /// Server -> Client, TimeResponse, answer to a ping.
+    Pong,
+}
+impl PacketKind {
+    pub fn to_byte(&self) -> u8 {
+        match self {
+            PacketKind::Input => 0,
+            PacketKind::Snapshot => 1,
+            PacketKind::Inputs => 2,
+            PacketKind::Watch => 3,
+            PacketKind::Probe => 4,
+            PacketKind::Info => 5,
+            PacketKind::Ping => 6,
+            PacketKind::Pong => 7,
+        }
+    }
+    pub fn from_byte(byte: u8) -> Option<PacketKind> {
+        match byte {
+            0 => Some(PacketKind::Input),
+            1 => Some(PacketKind::Snapshot),
+            2 => Some(PacketKind::Inputs),
+            3 => Some(PacketKind::Watch),
+            4 => Some(PacketKind::Probe),
+            5 => Some(PacketKind::Info),
+            6 => Some(PacketKind::Ping),
+            7 => Some(PacketKind::Pong),
+            _ => None,
+        }
+    }
+}
+/// One UDP datagram of the game traffic.
+///
+/// Sequence is always growing for the sender so older packets that came late can be dropped,
+/// ack is the newest sequence the sender received from the other side.
+#[derive(Debug, Clone)]
+pub struct Packet {
+    pub kind: PacketKind,
+    pub sequence: u32,
+    pub ack: u32,
+    pub payload: Vec<u8>,
+}
+impl Packet {
+    /// First bytes of every packet, anything else is ignored.
+    const MAGIC: [u8;2] = *b"NB";
+    /// Biggest packet that can be received.
+    pub const MAX_SIZE: usize = 65507;
+    pub fn new(kind: PacketKind, sequence: u32, ack: u32, payload: Vec<u8>) -> Packet {
+        Packet {
+            kind,
+            sequence,
+            ack,
+            payload,
+        }
+    }
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = ByteWriter::new();
+        out.bytes(&Self::MAGIC);
+        out.u8(self.kind.to_byte());
+        out.u32(self.sequence);
+        out.u32(self.ack);
+        out.bytes(&self.payload);
+        out.into_bytes()
+    }
+    pub fn from_bytes(bytes: &[u8]) -> Option<Packet> {
+        let mut input = ByteReader::new(bytes);
+        if [input.u8()?,input.u8()?] != Self::MAGIC {
+            return None;
+        }
+        Some(Packet {
+            kind: PacketKind::from_byte(input.u8()?)?,
+            sequence: input.u32()?,
+            ack: input.u32()?,
+            payload: input.rest().to_vec(),
+        })
+    }
+    /// Sends packet, errors are ignored because UDP can lose the packet anyway.
+    /// Returns how many bytes were send (0 if it failed).
+    pub fn send_to(&self, socket: &UdpSocket, addres: &SocketAddr) -> usize {
+        socket.send_to(&self.to_bytes(), addres).unwrap_or(0)
+    }
+    /// Sends packet through the network simulator if there is one.
+    pub fn send_through(&self, socket: &UdpSocket, simulator: Option<&NetworkSimulator>, addres: &SocketAddr) -> usize {
+        match simulator {
+            Some(simulator) => simulator.send(self.to_bytes(),*addres),
+            None => self.send_to(socket,addres),
+        }
+    }
+    /// Waits for next valid packet, broken packets are skipped.
+    /// Returns None when nothing came in time on a non blocking socket or one with a read timeout,
+    /// or when the socket cant be used anymore.
+    pub fn receive(socket: &UdpSocket) -> Option<(Packet,SocketAddr)> {
+        let mut buffer = vec![0;Self::MAX_SIZE];
+        loop {
+            match socket.recv_from(&mut buffer) {
+                Ok((size,addres)) => if let Some(packet) = Self::from_bytes(&buffer[..size]) {
+                    return Some((packet,addres));
+                },
+                Err(error) if Self::is_transient(error.kind()) => {},
+                Err(_) => return None,
+            }
+        }
+    }
+    /// Errors that are about one packet or one peer, the socket still works. Windows reports a
+    /// peer that went away (ICMP port unreachable) as a reset on the next receive.
+    fn is_transient(kind: ErrorKind) -> bool {
+        matches!(kind,
+            ErrorKind::ConnectionReset |
+            ErrorKind::ConnectionRefused |
+            ErrorKind::ConnectionAborted |
+            ErrorKind::Interrupted
+        )
+    }
+}
+/// Payload of a snapshot packet, instance is the character controlled by the receiving player.
+/// Map is already encoded so it can be shared between all players.
+pub fn snapshot_payload(instance: Option<u32>, map: &[u8]) -> Vec<u8> {
+    let mut out = ByteWriter::new();
+    match instance {
+        Some(id) => {out.bool(true); out.u32(id);},
+        None => out.bool(false),
+    }
+    out.bytes(map);
+    out.into_bytes()
+}
+/// Reads payload made by snapshot_payload.
+pub fn read_snapshot(payload: &[u8]) -> Option<(Option<u32>,Map)> {
+    let mut input = ByteReader::new(payload);
+    let instance = if input.bool()? {Some(input.u32()?)} else {None};
+    Some((instance,Map::from_bytes(input.rest())?))
+}
+/// Resolves addres of the server and opens a socket of the same ip version for the client.
+pub fn client_socket(addres: &str) -> Option<(UdpSocket,SocketAddr)> {
+    let server = addres.to_socket_addrs().ok()?.next()?;
+    let local = if server.is_ipv4() {"0.0.0.0:0"} else {"[::]:0"};
+    let socket = UdpSocket::bind(local).ok()?;
+    Some((socket,server))
+}
@@ -27,6 +27,55 @@ struct Args {
     /// Client name
     #[arg(short, long)]
     client: Option<String>,
+    /// Room to join
+    #[arg(long, default_value_t = 0)]
+    room: u32,
+    /// Lists rooms of the server and exits
+    #[arg(long, default_value_t = false)]
+    list_rooms: bool,
+    /// Creates a room with this name (password has to be the server password) and joins it
+    #[arg(long)]
+    create_room: Option<String>,
+    /// Password of the created room
+    #[arg(long, default_value_t = String::new())]
+    room_password: String,
+    /// Watch the room instead of playing
+    #[arg(long, default_value_t = false)]
+    spectate: bool,
+    /// Ticks the watched match is delayed by
+    #[arg(long, default_value_t = 0)]
+    spectate_delay: usize,
+    /// Password for admin commands, admin commands are disabled without it
+    #[arg(long, default_value_t = String::new())]
+    admin_password: String,
+    /// Sends admin command to the room and exits ("map 2", "kick name", "ban name", "pause",
+    /// "resume", "password text", "tps 60", "mute name", "unmute name")
+    #[arg(long)]
+    admin: Option<String>,
+    /// Name of the server shown on the local network
+    #[arg(long, default_value_t = String::from("Nebula"))]
+    server_name: String,
+    /// Lists servers on the local network (on the port of addres) and exits
+    #[arg(long, default_value_t = false)]
+    discover: bool,
+    /// Seconds a session token is valid for without being used
+    #[arg(long, default_value_t = 30)]
+    session_timeout: u64,
+    /// Seconds a player can be inactive before being removed
+    #[arg(long, default_value_t = 10)]
+    idle_timeout: u64,
+    /// Records the match hosted by the server in to a file
+    #[arg(long)]
+    record: Option<String>,
+    /// Plays a recorded match instead of joining a server
+    #[arg(long)]
+    replay: Option<String>,
+    /// Runs this many bots without a window instead of the client and prints theyr latencies
+    #[arg(long)]
+    bot: Option<usize>,
+    /// Seconds the bots play for
+    #[arg(long, default_value_t = 30)]
+    bot_duration: u64,
 
     /// Start without a server
     #[arg(short, long, default_value_t = false)]
@@ -41,6 +90,35 @@ struct Args {
     #[arg(long, default_value_t = false)]
     hurtboxes: bool,
 
+    /// Rollback netcode, server and clients have to use the same settings
+    #[arg(long, default_value_t = false)]
+    rollback: bool,
+    /// Ticks local inputs are delayed by in rollback mode
+    #[arg(long, default_value_t = 2)]
+    input_delay: usize,
+    /// Maximum ticks that can be rolled back
+    #[arg(long, default_value_t = 8)]
+    rollback_window: usize,
+
+    /// Simulated one way latency of sent UDP packets in milliseconds
+    #[arg(long, default_value_t = 0)]
+    latency: u64,
+    /// Simulated random latency added to every packet, up to this many milliseconds
+    #[arg(long, default_value_t = 0)]
+    jitter: u64,
+    /// Simulated chance (0 to 1) of losing a packet
+    #[arg(long, default_value_t = 0.0)]
+    loss: f32,
+    /// Simulated chance (0 to 1) of sending a packet twice
+    #[arg(long, default_value_t = 0.0)]
+    duplicate: f32,
+    /// Simulated chance (0 to 1) of a packet coming after the next ones
+    #[arg(long, default_value_t = 0.0)]
+    reorder: f32,
+    /// JSON file with simulated network conditions, used instead of the flags above
+    #[arg(long)]
+    network_config: Option<String>,
+
 }
 
 
@@ -54,21 +132,134 @@ fn main() {
     let addres = args.addres.clone();
     let opt_client = args.client.clone();
     let assets = args.assets.clone();
+    let rollback = if args.rollback {
+            Some(game::rollback::RollbackSettings {
+                input_delay: args.input_delay,
+                max_rollback: args.rollback_window,
+            })
+        } else {
+            None
+        };
+
+    let network = match &args.network_config {
+        Some(path) => match game::simulator::NetworkConditions::load(path) {
+            Some(conditions) => conditions,
+            None => {
+                println!("Loading network config failed");
+                return;
+            },
+        },
+        None => game::simulator::NetworkConditions {
+            latency: args.latency,
+            jitter: args.jitter,
+            loss: args.loss,
+            duplicate: args.duplicate,
+            reorder: args.reorder,
+        },
+    };
+
+    if let Some(path) = args.replay {
+        let mut client = client::Client::new(password,String::new(),addres,time,assets);
+        client.custom_rendering(args.coliders,args.hitboxes,args.hurtboxes);
+        if let Err(reason) = client.replay(path) {
+            println!("Playing replay failed: {reason}");
+        }
+        return;
+    }
+
+    if let Some(command) = args.admin {
+        let mut client = client::Client::new(password,String::new(),addres,time,assets);
+        client.room(args.room);
+        match game::networking::AdminCommand::parse(&command) {
+            Some(command) => if let Err(reason) = client.admin(args.admin_password,command) {
+                println!("Admin command was refused: {reason}");
+            },
+            None => println!("Unknown admin command"),
+        }
+        return;
+    }
+    if args.discover {
+        let port = addres.rsplit_once(':').and_then(|(_,port)|port.parse().ok()).unwrap_or(3621);
+        let servers = game::discovery::discover(port,std::time::Duration::from_secs(1));
+        if servers.is_empty() {
+            println!("No servers found");
+        }
+        for (addres,info) in servers {
+            let lock = if info.password {" (password)"} else {""};
+            println!("{addres}: {0} - map {1}, {2} players in {3} rooms{lock}",info.name,info.map_id,info.players,info.rooms);
+        }
+        return;
+    }
+    if args.list_rooms {
+        let client = client::Client::new(password,String::new(),addres,time,assets);
+        match client.list_rooms() {
+            Some(rooms) => for room in rooms {
+                let lock = if room.password {" (password)"} else {""};
+                println!("{0}: {1} - map {2}, {3} players{lock}",room.id,room.name,room.map_id,room.players);
+            },
+            None => println!("Listing rooms failed"),
+        }
+        return;
+    }
 
     let opt_server = if !args.no_server {
             Some( thread::spawn(move || {
-                    game::Game::new(args.password,args.addres,args.time,args.map,args.assets).start();
+                    let mut game = game::Game::new(args.password,args.addres,args.time,args.map,args.assets);
+                    if let Some(settings) = rollback {
+                        game.enable_rollback(settings);
+                    }
+                    game.session_timeout(std::time::Duration::from_secs(args.session_timeout));
+                    game.admin_password(args.admin_password);
+                    game.name(args.server_name);
+                    game.idle_timeout(std::time::Duration::from_secs(args.idle_timeout));
+                    game.simulate_network(network);
+                    if let Some(path) = args.record {
+                        game.record(path);
+                    }
+                    game.start();
                 }))
         } else {
             None
         };
 
+    if let Some(bots) = args.bot {
+        let name = opt_client.unwrap_or(String::from("bot"));
+        let mut client = client::Client::new(password,name,addres,time,assets);
+        client.room(args.room);
+        client.simulate_network(network);
+        // Local server needs a moment to start listening
+        if opt_server.is_some() {
+            thread::sleep(std::time::Duration::from_millis(100));
+        }
+        print!("{}",client.load_test(bots,std::time::Duration::from_secs(args.bot_duration)).render());
+        return;
+    }
     if let Some(client) = opt_client {
         let mut client = client::Client::new(password,client,addres,time,assets);
         client.custom_rendering(args.coliders,args.hitboxes,args.hurtboxes);
-        client.start()
+        if let Some(settings) = rollback {
+            client.enable_rollback(settings);
+        }
+        client.room(args.room);
+        client.simulate_network(network);
+        if args.spectate {
+            client.spectate(args.spectate_delay);
+        }
+        if let Some(name) = args.create_room {
+            // Local server needs a moment to start listening
+            if opt_server.is_some() {
+                thread::sleep(std::time::Duration::from_millis(100));
+            }
+            if let Err(reason) = client.create_room(name,args.room_password,args.map) {
+                println!("Creating room failed: {reason}");
+                return;
+            }
+        }
+        if let Err(reason) = client.start() {
+            println!("{reason}");
+        }
     } else if let Some(server) = opt_server {
-        let _ = server.join().unwrap();
+        server.join().unwrap();
     }
 
 }